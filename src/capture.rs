@@ -1,73 +1,30 @@
 // SPDX-License-Identifier: MPL-2.0
-#![allow(non_camel_case_types)]
-use std::os::raw::c_void;
-
-type winrt_capture = c_void;
-
-#[link(name = "libobs-winrt")]
-extern "C" {
-	fn winrt_capture_supported() -> bool;
-	fn winrt_capture_cursor_toggle_supported() -> bool;
-	fn winrt_capture_init_window(
-		cursor: bool,
-		window: isize,
-		client_area: bool,
-		force_sdr: bool,
-	) -> *mut winrt_capture;
-	fn winrt_capture_free(capture: *mut winrt_capture);
-	fn winrt_capture_active(capture: *const winrt_capture) -> bool;
-	fn winrt_capture_show_cursor(capture: *mut winrt_capture, show: bool);
-	fn winrt_capture_render(capture: *const winrt_capture);
-	fn winrt_capture_width(capture: *const winrt_capture) -> u32;
-	fn winrt_capture_height(capture: *const winrt_capture) -> u32;
-}
-
-#[repr(transparent)]
-pub struct WinrtCapture {
-	capture: *mut winrt_capture,
+#[cfg(windows)]
+mod winrt;
+
+#[cfg(windows)]
+pub use winrt::WinrtCapture;
+
+/// How a window should be captured, with any options the script left unset
+/// filled in from the source's settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureConfig {
+	pub cursor: bool,
+	pub client_area: bool,
+	pub force_sdr: bool,
 }
 
-impl WinrtCapture {
-	pub fn new(cursor: bool, window: isize, client_area: bool, force_sdr: bool) -> Option<Self> {
-		unsafe {
-			let capture = winrt_capture_init_window(cursor, window, client_area, force_sdr);
-			if capture.is_null() {
-				None
-			} else {
-				Some(Self { capture })
-			}
-		}
-	}
+/// A running capture of a window, as started by
+/// [`WindowProvider::capture`](crate::window::WindowProvider::capture).
+pub trait Capture {
+	/// Whether the capture is still receiving frames.
+	fn active(&self) -> bool;
 
-	pub fn active(&self) -> bool {
-		unsafe { winrt_capture_active(self.capture) }
-	}
+	/// Draws the latest frame. Must be called with the graphics context held,
+	/// as it is during `video_render`.
+	fn render(&self);
 
-	pub fn show_cursor(&mut self, show: bool) {
-		unsafe {
-			winrt_capture_show_cursor(self.capture, show);
-		}
-	}
-
-	pub fn render(&self) {
-		unsafe {
-			winrt_capture_render(self.capture);
-		}
-	}
-
-	pub fn width(&self) -> u32 {
-		unsafe { winrt_capture_width(self.capture) }
-	}
-
-	pub fn height(&self) -> u32 {
-		unsafe { winrt_capture_height(self.capture) }
-	}
-}
+	fn width(&self) -> u32;
 
-impl Drop for WinrtCapture {
-	fn drop(&mut self) {
-		unsafe {
-			winrt_capture_free(self.capture);
-		}
-	}
+	fn height(&self) -> u32;
 }
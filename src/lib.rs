@@ -16,6 +16,8 @@
 pub mod capture;
 pub mod lua;
 pub mod module;
+pub mod script_settings;
+pub mod selection;
 pub mod source;
 pub mod util;
 pub mod window;
@@ -1,19 +1,261 @@
 // SPDX-License-Identifier: MPL-2.0
-use mlua::Lua;
+mod json;
+mod logging;
+mod pattern;
+mod require;
+mod snapshot;
+mod wincat;
+
+use mlua::{
+	Compiler, FromLuaMulti, Function, IntoLuaMulti, Lua, LuaOptions, StdLib, Value, VmState,
+};
+use std::{
+	fmt,
+	path::PathBuf,
+	time::{Duration, Instant},
+};
+
+/// Globals that could be used to load code or touch the filesystem from
+/// inside a selector script.
+const UNSAFE_GLOBALS: &[&str] = &[
+	"require",
+	"package",
+	"load",
+	"loadstring",
+	"dofile",
+	"loadfile",
+];
 
 #[cold]
-pub(crate) fn setup_luau_context() -> Lua {
-	let context = Lua::new();
-	if let Err(err) = context
-		.globals()
-		.set("print", mlua::Function::wrap(lua_print))
+pub fn setup_luau_context() -> Lua {
+	let context = Lua::new_with(StdLib::ALL_SAFE ^ StdLib::PACKAGE, LuaOptions::default())
+		.expect("failed to create luau context");
 	{
-		error!("failed to setup print() logger: {}", err);
+		let globals = context.globals();
+		for name in UNSAFE_GLOBALS {
+			if let Err(err) = globals.raw_set(*name, mlua::Nil) {
+				error!("failed to remove {name} from globals: {}", err);
+			}
+		}
+		if let Err(err) = globals.set("print", mlua::Function::wrap(logging::print)) {
+			error!("failed to setup print() logger: {}", err);
+		}
+		if let Err(err) = globals.set("require", mlua::Function::wrap(require::require)) {
+			error!("failed to setup require(): {}", err);
+		}
+		if let Err(err) = wincat::install(&context) {
+			error!("failed to setup wincat library: {}", err);
+		}
+		if let Err(err) = snapshot::install_table_compat(&context) {
+			error!("failed to setup type() and pairs() for processes: {}", err);
+		}
+		// Otherwise the compiler inlines calls to the built-in type(), and
+		// the replacement above would be skipped.
+		context.set_compiler(
+			Compiler::new().set_mutable_globals(
+				snapshot::REPLACED_GLOBALS
+					.iter()
+					.map(|name| name.to_string())
+					.collect(),
+			),
+		);
+	}
+	context.set_interrupt(|lua| match lua.app_data_ref::<Deadline>() {
+		Some(deadline) if Instant::now() >= deadline.at => {
+			Err(mlua::Error::external(ScriptTimeout(deadline.budget)))
+		}
+		_ => Ok(VmState::Continue),
+	});
+	if let Err(err) = context.sandbox(true) {
+		error!("failed to enable luau sandbox: {}", err);
 	}
 	context
 }
 
-fn lua_print(_lua: &Lua, input: String) -> mlua::Result<()> {
-	info!("{input}");
-	Ok(())
+/// Returned when a script call runs past its execution budget.
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptTimeout(pub Duration);
+
+impl fmt::Display for ScriptTimeout {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"script exceeded its time limit of {}ms",
+			self.0.as_millis()
+		)
+	}
+}
+
+impl std::error::Error for ScriptTimeout {}
+
+/// Returned when a script allocates past its VM's memory limit.
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptOutOfMemory(pub usize);
+
+impl fmt::Display for ScriptOutOfMemory {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"script exceeded its memory limit of {} MiB",
+			self.0 / (1024 * 1024)
+		)
+	}
+}
+
+impl std::error::Error for ScriptOutOfMemory {}
+
+/// The memory limit currently applied to a VM, kept around for error reporting.
+struct MemoryLimit(usize);
+
+/// The point in time at which the interrupt handler aborts the running call.
+struct Deadline {
+	at: Instant,
+	budget: Duration,
+}
+
+/// Calls `func`, aborting it with [`ScriptTimeout`] if it runs longer than
+/// `budget`.
+pub(crate) fn call_with_budget<'lua, A, R>(
+	lua: &'lua Lua,
+	func: &Function<'lua>,
+	args: A,
+	budget: Duration,
+) -> anyhow::Result<R>
+where
+	A: IntoLuaMulti<'lua>,
+	R: FromLuaMulti<'lua>,
+{
+	with_budget(lua, budget, || func.call::<A, R>(args))
+}
+
+/// Runs `run`, which may call into `lua`, aborting any script code it runs
+/// with [`ScriptTimeout`] if it takes longer than `budget`. Loading a script
+/// runs its top-level code and that of any modules it requires, so needs a
+/// budget as much as calling into it does.
+pub(crate) fn with_budget<R>(
+	lua: &Lua,
+	budget: Duration,
+	run: impl FnOnce() -> mlua::Result<R>,
+) -> anyhow::Result<R> {
+	lua.set_app_data(Deadline {
+		at: Instant::now() + budget,
+		budget,
+	});
+	scopeguard::defer! {
+		lua.remove_app_data::<Deadline>();
+	};
+	run().map_err(|err| script_error(lua, err))
+}
+
+/// Limits the memory used by `lua` to `limit` bytes.
+pub(crate) fn set_memory_limit(lua: &Lua, limit: usize) {
+	match lua.set_memory_limit(limit) {
+		Ok(_) => {
+			lua.set_app_data(MemoryLimit(limit));
+		}
+		Err(err) => error!("failed to set memory limit: {}", err),
+	}
+}
+
+/// Gets the `source:line: ` position of the script code that called into the
+/// currently running Rust function, for use in error messages.
+pub(crate) fn caller_location(lua: &Lua) -> Option<String> {
+	let debug = lua.inspect_stack(1)?;
+	let line = debug.curr_line();
+	let source = debug.source().short_src?.into_owned();
+	Some(format!("{source}:{line}: "))
+}
+
+/// Gets the `source:line` at which a Lua function was defined.
+pub(crate) fn function_location(func: &Function) -> Option<String> {
+	let info = func.info();
+	Some(format!("{}:{}", info.short_src?, info.line_defined?))
+}
+
+/// Describes a value returned by a script for error messages, with a hint
+/// for the mistakes that are easy to make.
+pub(crate) fn describe_value(value: &Value) -> String {
+	match value {
+		Value::String(string) => format!("a string ({:?})", string.to_string_lossy()),
+		Value::Number(number) => format!("the non-integer number {number}"),
+		Value::Table(table) if table.raw_len() > 0 => {
+			"an array containing something that isn't a window".to_owned()
+		}
+		Value::Table(table) if table.contains_key("window").unwrap_or(false) => {
+			"a { window = ... } table whose window or options are invalid".to_owned()
+		}
+		Value::Table(_) => "a table without an hwnd or window field".to_owned(),
+		Value::UserData(userdata) if userdata.is::<ProcessView>() => {
+			"a Process; return one of its windows, such as proc.main, instead".to_owned()
+		}
+		other => format!("a {}", other.type_name()),
+	}
+}
+
+/// Takes the delay the script last asked for with `wincat.set_next_check`,
+/// if any.
+pub(crate) fn take_next_check(lua: &Lua) -> Option<f32> {
+	lua.remove_app_data::<wincat::NextCheck>()
+		.map(|next| next.0)
+}
+
+/// Runs `run` with the helpers in the `wincat` library searching `snapshot`,
+/// the one passed to `select_window`, instead of the latest one.
+pub(crate) fn with_call_snapshot<R>(lua: &Lua, snapshot: &Snapshot, run: impl FnOnce() -> R) -> R {
+	lua.set_app_data(wincat::CallSnapshot(snapshot.clone()));
+	scopeguard::defer! {
+		lua.remove_app_data::<wincat::CallSnapshot>();
+	};
+	run()
+}
+
+/// Sets the OBS source name that the script's log messages are prefixed with.
+pub(crate) fn set_source_name(lua: &Lua, name: &str) {
+	let stale = lua
+		.app_data_ref::<logging::SourceName>()
+		.is_none_or(|current| current.0 != name);
+	if stale {
+		lua.set_app_data(logging::SourceName(name.to_owned()));
+	}
+}
+
+/// Sets the directories that `require` may load modules from.
+pub(crate) fn set_module_roots(lua: &Lua, roots: Vec<PathBuf>) {
+	lua.set_app_data(require::ModuleRoots(roots));
+}
+
+pub(crate) use json::{from_json, to_json};
+pub(crate) use require::modules_changed;
+pub(crate) use snapshot::detached_window_view;
+pub use snapshot::{process_list, ProcessView, Snapshot, WindowView};
+
+/// Converts a Lua error into an [`anyhow::Error`], surfacing timeouts and
+/// memory exhaustion as [`ScriptTimeout`] and [`ScriptOutOfMemory`].
+pub(crate) fn script_error(lua: &Lua, err: mlua::Error) -> anyhow::Error {
+	fn find_timeout(err: &mlua::Error) -> Option<ScriptTimeout> {
+		match err {
+			mlua::Error::CallbackError { cause, .. } => find_timeout(cause),
+			mlua::Error::WithContext { cause, .. } => find_timeout(cause),
+			mlua::Error::ExternalError(err) => err.downcast_ref::<ScriptTimeout>().copied(),
+			_ => None,
+		}
+	}
+
+	fn is_out_of_memory(err: &mlua::Error) -> bool {
+		match err {
+			mlua::Error::MemoryError(_) => true,
+			mlua::Error::CallbackError { cause, .. } => is_out_of_memory(cause),
+			mlua::Error::WithContext { cause, .. } => is_out_of_memory(cause),
+			_ => false,
+		}
+	}
+
+	if let Some(timeout) = find_timeout(&err) {
+		anyhow::Error::new(timeout)
+	} else if is_out_of_memory(&err) {
+		let limit = lua.app_data_ref::<MemoryLimit>().map_or(0, |limit| limit.0);
+		anyhow::Error::new(ScriptOutOfMemory(limit))
+	} else {
+		anyhow::Error::new(err)
+	}
 }
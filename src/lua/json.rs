@@ -0,0 +1,292 @@
+// SPDX-License-Identifier: MPL-2.0
+use mlua::{DeserializeOptions, Lua, LuaSerdeExt, Table, Value};
+use serde_json::{Map, Number};
+use std::ffi::c_void;
+
+/// The largest integer a Luau number holds exactly.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Converts a Lua value to JSON, or `None` if it has no JSON equivalent.
+///
+/// Unlike deserializing through serde, this accepts tables with keys that
+/// aren't strings, such as a table indexed by pid: integer, number and boolean
+/// keys are written as strings, and keys of other types are skipped, as are
+/// functions, threads and cycles. Tables whose keys are exactly `1..=n`
+/// become arrays, and every other table an object, with keys in sorted order.
+/// Serializable userdata, like processes and windows, is written as its data.
+pub(crate) fn to_json(lua: &Lua, value: Value) -> Option<serde_json::Value> {
+	to_json_inner(lua, value, &mut Vec::new())
+}
+
+/// Converts JSON written by [`to_json`] back to a Lua value. Object keys
+/// that spell out an integer become integer keys again, so a table indexed by
+/// pid round-trips, at the cost of turning string keys like `"42"` into
+/// numbers.
+pub(crate) fn from_json<'lua>(
+	lua: &'lua Lua,
+	value: &serde_json::Value,
+) -> mlua::Result<Value<'lua>> {
+	Ok(match value {
+		serde_json::Value::Null => Value::Nil,
+		serde_json::Value::Bool(value) => Value::Boolean(*value),
+		serde_json::Value::Number(number) => match number.as_i64() {
+			Some(integer) => Value::Number(integer as f64),
+			None => Value::Number(number.as_f64().unwrap_or(f64::NAN)),
+		},
+		serde_json::Value::String(string) => Value::String(lua.create_string(string)?),
+		serde_json::Value::Array(values) => {
+			let table = lua.create_table_with_capacity(values.len(), 0)?;
+			for (index, value) in values.iter().enumerate() {
+				table.raw_set(index + 1, from_json(lua, value)?)?;
+			}
+			Value::Table(table)
+		}
+		serde_json::Value::Object(entries) => {
+			let table = lua.create_table_with_capacity(0, entries.len())?;
+			for (key, value) in entries {
+				let value = from_json(lua, value)?;
+				match key.parse::<i64>() {
+					Ok(integer) if integer.to_string() == *key => table.raw_set(integer, value)?,
+					_ => table.raw_set(key.as_str(), value)?,
+				}
+			}
+			Value::Table(table)
+		}
+	})
+}
+
+fn to_json_inner(
+	lua: &Lua,
+	value: Value,
+	path: &mut Vec<*const c_void>,
+) -> Option<serde_json::Value> {
+	match value {
+		Value::Nil => Some(serde_json::Value::Null),
+		Value::Boolean(value) => Some(serde_json::Value::Bool(value)),
+		Value::Integer(value) => Some(serde_json::Value::from(value)),
+		Value::Number(value) => match whole(value) {
+			Some(integer) => Some(serde_json::Value::from(integer)),
+			None => Number::from_f64(value).map(serde_json::Value::Number),
+		},
+		Value::String(string) => Some(serde_json::Value::String(
+			string.to_string_lossy().into_owned(),
+		)),
+		Value::Table(table) => {
+			let pointer = table.to_pointer();
+			if path.contains(&pointer) {
+				return None;
+			}
+			path.push(pointer);
+			let json = table_to_json(lua, table, path);
+			path.pop();
+			json
+		}
+		Value::UserData(_) => {
+			let options = DeserializeOptions::new()
+				.deny_unsupported_types(false)
+				.deny_recursive_tables(false)
+				.sort_keys(true);
+			lua.from_value_with::<serde_json::Value>(value, options)
+				.ok()
+		}
+		_ => None,
+	}
+}
+
+fn table_to_json(
+	lua: &Lua,
+	table: Table,
+	path: &mut Vec<*const c_void>,
+) -> Option<serde_json::Value> {
+	let mut entries = Vec::new();
+	for pair in table.pairs::<Value, Value>() {
+		let (key, value) = pair.ok()?;
+		let key = match key {
+			Value::String(key) => Key::String(key.to_string_lossy().into_owned()),
+			Value::Integer(key) => Key::Integer(key.into()),
+			Value::Number(key) => match whole(key) {
+				Some(key) => Key::Integer(key),
+				None if key.is_finite() => Key::String(key.to_string()),
+				None => continue,
+			},
+			Value::Boolean(key) => Key::String(key.to_string()),
+			_ => continue,
+		};
+		if let Some(value) = to_json_inner(lua, value, path) {
+			entries.push((key, value));
+		}
+	}
+	// Keys are unique, so if there are n of them, all in 1..=n, they are
+	// exactly 1..=n, though pairs may visit them in any order.
+	let len = entries.len() as i64;
+	let is_array = len > 0
+		&& entries
+			.iter()
+			.all(|(key, _)| matches!(key, Key::Integer(key) if (1..=len).contains(key)));
+	if is_array {
+		let mut values = vec![serde_json::Value::Null; entries.len()];
+		for (key, value) in entries {
+			if let Key::Integer(key) = key {
+				values[key as usize - 1] = value;
+			}
+		}
+		return Some(serde_json::Value::Array(values));
+	}
+	// An integer key and a string spelling the same number, like 1 and "1",
+	// end up as the same object key. The integer's value wins, since that's
+	// the key from_json restores.
+	entries.sort_by_key(|(key, _)| matches!(key, Key::Integer(_)));
+	Some(serde_json::Value::Object(
+		entries
+			.into_iter()
+			.map(|(key, value)| (key.into_string(), value))
+			.collect::<Map<_, _>>(),
+	))
+}
+
+enum Key {
+	Integer(i64),
+	String(String),
+}
+
+impl Key {
+	fn into_string(self) -> String {
+		match self {
+			Self::Integer(key) => key.to_string(),
+			Self::String(key) => key,
+		}
+	}
+}
+
+/// Gets `value` as an integer, if it is a whole number Luau holds exactly.
+fn whole(value: f64) -> Option<i64> {
+	(value.fract() == 0.0 && value.abs() <= MAX_SAFE_INTEGER).then_some(value as i64)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	fn eval<'lua>(lua: &'lua Lua, chunk: &str) -> Value<'lua> {
+		lua.load(chunk).eval().unwrap()
+	}
+
+	/// Converts `chunk`'s value to JSON text and back, like persisted state.
+	fn round_trip<'lua>(lua: &'lua Lua, chunk: &str) -> Table<'lua> {
+		let json = to_json(lua, eval(lua, chunk)).unwrap();
+		let json = serde_json::from_str(&serde_json::to_string(&json).unwrap()).unwrap();
+		match from_json(lua, &json).unwrap() {
+			Value::Table(table) => table,
+			other => panic!("expected a table, got {other:?}"),
+		}
+	}
+
+	#[test]
+	fn integer_keys_become_strings_and_back() {
+		let lua = Lua::new();
+		let value = eval(&lua, r#"return { [100] = { name = "a" }, [200] = true }"#);
+		assert_eq!(
+			to_json(&lua, value),
+			Some(json!({ "100": { "name": "a" }, "200": true }))
+		);
+		let state = round_trip(&lua, r#"return { [100] = { name = "a" }, [200] = true }"#);
+		assert_eq!(
+			state
+				.get::<_, Table>(100)
+				.unwrap()
+				.get::<_, String>("name")
+				.unwrap(),
+			"a"
+		);
+		assert!(state.get::<_, bool>(200).unwrap());
+		assert!(state.get::<_, Option<bool>>("200").unwrap().is_none());
+	}
+
+	#[test]
+	fn only_keys_1_to_n_make_arrays() {
+		let lua = Lua::new();
+		let json = |chunk| to_json(&lua, eval(&lua, chunk));
+		assert_eq!(
+			json(r#"return { "a", "b", "c" }"#),
+			Some(json!(["a", "b", "c"]))
+		);
+		assert_eq!(
+			json(r#"return { [3] = "c", [1] = "a", [2] = "b" }"#),
+			Some(json!(["a", "b", "c"]))
+		);
+		assert_eq!(
+			json(r#"return { [1] = "a", [3] = "c" }"#),
+			Some(json!({ "1": "a", "3": "c" }))
+		);
+		assert_eq!(
+			json(r#"return { "a", x = 1 }"#),
+			Some(json!({ "1": "a", "x": 1 }))
+		);
+		assert_eq!(json("return {}"), Some(json!({})));
+
+		let state = round_trip(
+			&lua,
+			r#"return { list = { "a", "b" }, sparse = { [2] = "b" } }"#,
+		);
+		let list = state.get::<_, Table>("list").unwrap();
+		assert_eq!(list.raw_len(), 2);
+		assert_eq!(list.get::<_, String>(2).unwrap(), "b");
+		let sparse = state.get::<_, Table>("sparse").unwrap();
+		assert_eq!(sparse.get::<_, String>(2).unwrap(), "b");
+	}
+
+	#[test]
+	fn skips_functions_and_cycles() {
+		let lua = Lua::new();
+		let value = eval(
+			&lua,
+			r#"
+				local state = { count = 1, callback = print, nested = { ok = true } }
+				state.self = state
+				state.nested.parent = state
+				return state
+			"#,
+		);
+		assert_eq!(
+			to_json(&lua, value),
+			Some(json!({ "count": 1, "nested": { "ok": true } }))
+		);
+		assert_eq!(to_json(&lua, eval(&lua, "return print")), None);
+	}
+
+	#[test]
+	fn other_keys() {
+		let lua = Lua::new();
+		let value = eval(
+			&lua,
+			r#"return { [1.5] = "half", [true] = "yes", [print] = "no" }"#,
+		);
+		assert_eq!(
+			to_json(&lua, value),
+			Some(json!({ "1.5": "half", "true": "yes" }))
+		);
+		let state = round_trip(&lua, r#"return { [1.5] = "half", ["042"] = "padded" }"#);
+		assert_eq!(state.get::<_, String>("1.5").unwrap(), "half");
+		assert_eq!(state.get::<_, String>("042").unwrap(), "padded");
+		assert!(state.get::<_, Option<String>>(42).unwrap().is_none());
+	}
+
+	#[test]
+	fn integer_keys_win_over_equal_strings() {
+		let lua = Lua::new();
+		for chunk in [
+			r#"return { ["1"] = "string", [1] = "integer" }"#,
+			r#"return { [1] = "integer", ["1"] = "string" }"#,
+		] {
+			assert_eq!(
+				to_json(&lua, eval(&lua, chunk)),
+				Some(json!({ "1": "integer" }))
+			);
+		}
+		// Strings that spell an integer come back as integer keys.
+		let state = round_trip(&lua, r#"return { ["7"] = "seven", ["-2"] = "minus two" }"#);
+		assert_eq!(state.get::<_, String>(7).unwrap(), "seven");
+		assert_eq!(state.get::<_, String>(-2).unwrap(), "minus two");
+	}
+}
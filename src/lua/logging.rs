@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: MPL-2.0
+use ahash::AHashMap;
+use log::Level;
+use mlua::{Lua, Table, Value, Variadic};
+use std::time::{Duration, Instant};
+
+/// How long an identical message is suppressed for after being logged.
+const REPEAT_WINDOW: Duration = Duration::from_secs(10);
+/// How many distinct recent messages are tracked at most.
+const MAX_TRACKED: usize = 256;
+
+/// The name of the OBS source a VM belongs to, used to prefix its logs.
+pub(crate) struct SourceName(pub String);
+
+struct Seen {
+	last: Instant,
+	suppressed: u32,
+}
+
+/// Recently logged messages, used to rate-limit identical ones.
+#[derive(Default)]
+struct RecentMessages(AHashMap<(Level, String), Seen>);
+
+impl RecentMessages {
+	/// Records `message`, returning `None` if it should be suppressed, or
+	/// how many identical messages were suppressed since it was last logged.
+	fn record(&mut self, level: Level, message: &str) -> Option<u32> {
+		let now = Instant::now();
+		let key = (level, message.to_owned());
+		if let Some(seen) = self.0.get_mut(&key) {
+			if now.duration_since(seen.last) < REPEAT_WINDOW {
+				seen.suppressed += 1;
+				return None;
+			}
+			let suppressed = seen.suppressed;
+			*seen = Seen {
+				last: now,
+				suppressed: 0,
+			};
+			return Some(suppressed);
+		}
+		if self.0.len() >= MAX_TRACKED {
+			self.0
+				.retain(|_, seen| now.duration_since(seen.last) < REPEAT_WINDOW);
+		}
+		// Everything may still be recent, e.g. a script logging a timestamp in
+		// a loop, so make room by forgetting the oldest message.
+		if self.0.len() >= MAX_TRACKED {
+			let oldest = self
+				.0
+				.iter()
+				.min_by_key(|(_, seen)| seen.last)
+				.map(|(key, _)| key.clone());
+			if let Some(oldest) = oldest {
+				self.0.remove(&oldest);
+			}
+		}
+		self.0.insert(key, Seen {
+			last: now,
+			suppressed: 0,
+		});
+		Some(0)
+	}
+}
+
+/// Creates the `wincat.log` table.
+pub(crate) fn create_table(lua: &Lua) -> mlua::Result<Table<'_>> {
+	let table = lua.create_table()?;
+	for (name, level) in [
+		("debug", Level::Debug),
+		("info", Level::Info),
+		("warn", Level::Warn),
+		("error", Level::Error),
+	] {
+		table.set(
+			name,
+			lua.create_function(move |lua, args: Variadic<Value>| emit(lua, level, args))?,
+		)?;
+	}
+	Ok(table)
+}
+
+/// A variadic `print` that logs at info level.
+pub(crate) fn print(lua: &Lua, args: Variadic<Value>) -> mlua::Result<()> {
+	emit(lua, Level::Info, args)
+}
+
+fn emit(lua: &Lua, level: Level, args: Variadic<Value>) -> mlua::Result<()> {
+	let mut message = String::new();
+	for (index, arg) in args.iter().enumerate() {
+		if index > 0 {
+			message.push('\t');
+		}
+		match arg {
+			Value::String(string) => message.push_str(&string.to_string_lossy()),
+			other => message.push_str(&other.to_string()?),
+		}
+	}
+	if lua.app_data_ref::<RecentMessages>().is_none() {
+		lua.set_app_data(RecentMessages::default());
+	}
+	let suppressed = match lua.app_data_mut::<RecentMessages>() {
+		Some(mut recent) => recent.record(level, &message),
+		None => Some(0),
+	};
+	let name = lua
+		.app_data_ref::<SourceName>()
+		.map(|name| name.0.clone())
+		.unwrap_or_default();
+	match suppressed {
+		None => {}
+		Some(0) => log!(level, "[{}] {}", name, message),
+		Some(count) => log!(
+			level,
+			"[{}] {} ({} identical messages suppressed)",
+			name,
+			message,
+			count
+		),
+	}
+	Ok(())
+}
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: MPL-2.0
+use ahash::AHashMap;
+use mlua::{Lua, MetaMethod, UserData, UserDataMethods, Value};
+use regex::Regex;
+
+/// The most compiled patterns kept per VM before the cache is flushed.
+const CACHE_CAPACITY: usize = 256;
+
+/// Patterns already compiled in a VM, keyed by their source.
+#[derive(Default)]
+struct RegexCache(AHashMap<String, Regex>);
+
+/// A compiled regular expression, as returned by `wincat.regex`.
+pub(crate) struct LuaRegex(Regex);
+
+impl UserData for LuaRegex {
+	fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+		methods.add_method("match", |_, this, text: mlua::String| {
+			Ok(this.0.is_match(text.to_str()?))
+		});
+		methods.add_method(
+			"find",
+			|_, this, (text, init): (mlua::String, Option<usize>)| {
+				let text = text.to_str()?;
+				let start = init.unwrap_or(1).saturating_sub(1);
+				if start > text.len() || !text.is_char_boundary(start) {
+					return Ok((None, None));
+				}
+				Ok(match this.0.find_at(text, start) {
+					Some(found) => (Some(found.start() + 1), Some(found.end())),
+					None => (None, None),
+				})
+			},
+		);
+		methods.add_method("captures", |lua, this, text: mlua::String| {
+			let text = text.to_str()?;
+			let captures = match this.0.captures(text) {
+				Some(captures) => captures,
+				None => return Ok(Value::Nil),
+			};
+			let table = lua.create_table()?;
+			for (index, group) in captures.iter().enumerate() {
+				if let Some(group) = group {
+					table.raw_set(index, group.as_str())?;
+				}
+			}
+			for name in this.0.capture_names().flatten() {
+				if let Some(group) = captures.name(name) {
+					table.raw_set(name, group.as_str())?;
+				}
+			}
+			Ok(Value::Table(table))
+		});
+		methods.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+			Ok(format!("regex({})", this.0.as_str()))
+		});
+	}
+}
+
+/// Compiles `pattern`, reusing a previous compilation from this VM if there
+/// is one.
+pub(crate) fn compile(lua: &Lua, pattern: String) -> mlua::Result<LuaRegex> {
+	if let Some(regex) = lua
+		.app_data_ref::<RegexCache>()
+		.and_then(|cache| cache.0.get(&pattern).cloned())
+	{
+		return Ok(LuaRegex(regex));
+	}
+	let regex = Regex::new(&pattern).map_err(|err| {
+		mlua::Error::runtime(format!(
+			"{}invalid regex: {}",
+			super::caller_location(lua).unwrap_or_default(),
+			err
+		))
+	})?;
+	if lua.app_data_ref::<RegexCache>().is_none() {
+		lua.set_app_data(RegexCache::default());
+	}
+	if let Some(mut cache) = lua.app_data_mut::<RegexCache>() {
+		if cache.0.len() >= CACHE_CAPACITY {
+			cache.0.clear();
+		}
+		cache.0.insert(pattern, regex.clone());
+	}
+	Ok(LuaRegex(regex))
+}
@@ -0,0 +1,135 @@
+// SPDX-License-Identifier: MPL-2.0
+use ahash::{AHashMap, AHashSet};
+use mlua::{Lua, RegistryKey, Value};
+use std::{
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+const EXTENSIONS: &[&str] = &["luau", "lua"];
+
+/// The directories that `require` is allowed to load modules from.
+pub(crate) struct ModuleRoots(pub Vec<PathBuf>);
+
+struct CachedModule {
+	modified: SystemTime,
+	value: RegistryKey,
+}
+
+/// Modules that have already been loaded into a VM, keyed by their path.
+#[derive(Default)]
+pub(crate) struct ModuleCache {
+	modules: AHashMap<PathBuf, CachedModule>,
+	loading: AHashSet<PathBuf>,
+}
+
+pub(crate) fn require<'lua>(lua: &'lua Lua, name: String) -> mlua::Result<Value<'lua>> {
+	let path = resolve(lua, &name)?;
+	let modified = std::fs::metadata(&path)
+		.and_then(|metadata| metadata.modified())
+		.map_err(mlua::Error::external)?;
+	if let Some(cache) = lua.app_data_ref::<ModuleCache>() {
+		if let Some(module) = cache.modules.get(&path) {
+			if module.modified == modified {
+				return lua.registry_value(&module.value);
+			}
+		}
+		if cache.loading.contains(&path) {
+			return Err(mlua::Error::runtime(format!(
+				"cyclic require of module '{name}'"
+			)));
+		}
+	}
+
+	let source = std::fs::read_to_string(&path).map_err(mlua::Error::external)?;
+	with_cache(lua, |cache| cache.loading.insert(path.clone()));
+	let result = lua
+		.load(&source)
+		.set_name(format!("@{}", path.display()))
+		.eval::<Value>();
+	with_cache(lua, |cache| cache.loading.remove(&path));
+	let value = result?;
+
+	let key = lua.create_registry_value(value.clone())?;
+	if let Some(old) = with_cache(lua, |cache| {
+		cache.modules.insert(path, CachedModule {
+			modified,
+			value: key,
+		})
+	}) {
+		lua.remove_registry_value(old.value)?;
+	}
+	Ok(value)
+}
+
+/// Checks whether any module loaded into `lua` has changed on disk since it
+/// was loaded.
+pub(crate) fn modules_changed(lua: &Lua) -> bool {
+	let cache = match lua.app_data_ref::<ModuleCache>() {
+		Some(cache) => cache,
+		None => return false,
+	};
+	cache.modules.iter().any(|(path, module)| {
+		std::fs::metadata(path)
+			.and_then(|metadata| metadata.modified())
+			.map_or(true, |modified| modified != module.modified)
+	})
+}
+
+fn with_cache<R>(lua: &Lua, f: impl FnOnce(&mut ModuleCache) -> R) -> R {
+	if lua.app_data_ref::<ModuleCache>().is_none() {
+		lua.set_app_data(ModuleCache::default());
+	}
+	let mut cache = lua
+		.app_data_mut::<ModuleCache>()
+		.expect("module cache was just created");
+	f(&mut cache)
+}
+
+/// Finds the file for module `name` under the allowed roots, refusing
+/// anything that would escape them.
+fn resolve(lua: &Lua, name: &str) -> mlua::Result<PathBuf> {
+	let is_valid_segment = |segment: &str| {
+		!segment.is_empty()
+			&& segment
+				.chars()
+				.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+	};
+	if !name.split(['/', '.']).all(is_valid_segment) {
+		return Err(mlua::Error::runtime(format!(
+			"invalid module name '{name}'"
+		)));
+	}
+	let relative: PathBuf = name.split(['/', '.']).collect();
+	let roots = lua
+		.app_data_ref::<ModuleRoots>()
+		.map(|roots| roots.0.clone())
+		.unwrap_or_default();
+	for root in &roots {
+		let root = match root.canonicalize() {
+			Ok(root) => root,
+			Err(_) => continue,
+		};
+		for extension in EXTENSIONS {
+			let candidate = root.join(&relative).with_extension(extension);
+			if let Some(path) = contained_file(&root, &candidate) {
+				return Ok(path);
+			}
+		}
+	}
+	Err(mlua::Error::runtime(format!(
+		"module '{name}' not found in {}",
+		roots
+			.iter()
+			.map(|root| root.display().to_string())
+			.collect::<Vec<_>>()
+			.join(", ")
+	)))
+}
+
+/// Returns the canonical path of `candidate` if it is a file inside `root`,
+/// following symlinks.
+fn contained_file(root: &Path, candidate: &Path) -> Option<PathBuf> {
+	let path = candidate.canonicalize().ok()?;
+	(path.starts_with(root) && path.is_file()).then_some(path)
+}
@@ -0,0 +1,344 @@
+// SPDX-License-Identifier: MPL-2.0
+use crate::window::{Process, Window};
+use mlua::{
+	AnyUserData, Function, IntoLua, IntoLuaMulti, Lua, MetaMethod, MultiValue, Table, UserData,
+	UserDataFields, UserDataMethods, Value,
+};
+use serde::{Serialize, Serializer};
+use std::sync::Arc;
+
+/// An immutable list of processes, shared between the polling thread and
+/// every script that reads it.
+pub type Snapshot = Arc<Vec<Process>>;
+
+/// A read-only view of one process in a [`Snapshot`]. Fields are only
+/// converted into Lua values when a script reads them.
+#[derive(Clone)]
+pub struct ProcessView {
+	snapshot: Snapshot,
+	index: usize,
+}
+
+#[derive(Clone, Copy)]
+enum WindowSlot {
+	Main,
+	Listed(usize),
+}
+
+/// A read-only view of one window in a [`Snapshot`], either a process's main
+/// window or one of its listed windows.
+#[derive(Clone)]
+pub struct WindowView {
+	snapshot: Snapshot,
+	process: usize,
+	slot: WindowSlot,
+}
+
+impl ProcessView {
+	pub fn process(&self) -> &Process {
+		&self.snapshot[self.index]
+	}
+
+	fn window(&self, slot: WindowSlot) -> WindowView {
+		WindowView {
+			snapshot: self.snapshot.clone(),
+			process: self.index,
+			slot,
+		}
+	}
+}
+
+impl WindowView {
+	pub fn window(&self) -> &Window {
+		let process = &self.snapshot[self.process];
+		match self.slot {
+			WindowSlot::Main => process
+				.main
+				.as_ref()
+				.expect("main window view of a process without a main window"),
+			WindowSlot::Listed(index) => &process.windows[index],
+		}
+	}
+}
+
+/// The fields of a [`ProcessView`] or [`WindowView`], which scripts used to
+/// get as plain tables.
+trait View {
+	/// The names of every field, in the order they are iterated.
+	const FIELDS: &'static [&'static str];
+
+	/// Reads the field named `key`, or `nil` if there is no such field.
+	fn field<'lua>(&self, lua: &'lua Lua, key: &[u8]) -> mlua::Result<Value<'lua>>;
+
+	/// Copies the fields that are set into a table.
+	fn fields_table<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Table<'lua>> {
+		let table = lua.create_table_with_capacity(0, Self::FIELDS.len())?;
+		for name in Self::FIELDS {
+			table.raw_set(*name, self.field(lua, name.as_bytes())?)?;
+		}
+		Ok(table)
+	}
+}
+
+impl View for ProcessView {
+	const FIELDS: &'static [&'static str] = &[
+		"name",
+		"pid",
+		"exe_path",
+		"command_line",
+		"parent_pid",
+		"start_time",
+		"session_id",
+		"user",
+		"main",
+		"windows",
+	];
+
+	fn field<'lua>(&self, lua: &'lua Lua, key: &[u8]) -> mlua::Result<Value<'lua>> {
+		let process = self.process();
+		match key {
+			b"name" => process.name.as_str().into_lua(lua),
+			b"pid" => process.pid.into_lua(lua),
+			b"exe_path" => process.exe_path.as_deref().into_lua(lua),
+			b"command_line" => process.command_line.as_deref().into_lua(lua),
+			b"parent_pid" => process.parent_pid.into_lua(lua),
+			b"start_time" => process.start_time.into_lua(lua),
+			b"session_id" => process.session_id.into_lua(lua),
+			b"user" => process.user.as_deref().into_lua(lua),
+			b"main" => match process.main {
+				Some(_) => lua
+					.create_ser_userdata(self.window(WindowSlot::Main))
+					.map(Value::UserData),
+				None => Ok(Value::Nil),
+			},
+			b"windows" => {
+				let windows = lua.create_table_with_capacity(process.windows.len(), 0)?;
+				for index in 0..process.windows.len() {
+					windows.raw_push(
+						lua.create_ser_userdata(self.window(WindowSlot::Listed(index)))?,
+					)?;
+				}
+				Ok(Value::Table(windows))
+			}
+			_ => Ok(Value::Nil),
+		}
+	}
+}
+
+impl View for WindowView {
+	const FIELDS: &'static [&'static str] = &[
+		"title",
+		"class_name",
+		"hwnd",
+		"visible",
+		"x",
+		"y",
+		"width",
+		"height",
+		"minimized",
+		"maximized",
+		"cloaked",
+		"is_tool_window",
+		"owner_hwnd",
+		"z_order",
+		"is_foreground",
+		"monitor",
+	];
+
+	fn field<'lua>(&self, lua: &'lua Lua, key: &[u8]) -> mlua::Result<Value<'lua>> {
+		let window = self.window();
+		match key {
+			b"title" => window.title.as_str().into_lua(lua),
+			b"class_name" => window.class_name.as_str().into_lua(lua),
+			b"hwnd" => window.hwnd.into_lua(lua),
+			b"visible" => window.visible.into_lua(lua),
+			b"x" => window.x.into_lua(lua),
+			b"y" => window.y.into_lua(lua),
+			b"width" => window.width.into_lua(lua),
+			b"height" => window.height.into_lua(lua),
+			b"minimized" => window.minimized.into_lua(lua),
+			b"maximized" => window.maximized.into_lua(lua),
+			b"cloaked" => window.cloaked.into_lua(lua),
+			b"is_tool_window" => window.is_tool_window.into_lua(lua),
+			b"owner_hwnd" => window.owner_hwnd.into_lua(lua),
+			b"z_order" => window.z_order.into_lua(lua),
+			b"is_foreground" => window.is_foreground.into_lua(lua),
+			b"monitor" => window.monitor.into_lua(lua),
+			_ => Ok(Value::Nil),
+		}
+	}
+}
+
+/// Implements `__iter`, so that `for key, value in view do` lists the
+/// fields, as it did for tables.
+fn fields_iter<'lua>(
+	lua: &'lua Lua,
+	view: &impl View,
+) -> mlua::Result<(Function<'lua>, Table<'lua>, Value<'lua>)> {
+	let next = lua.globals().get::<_, Function>("next")?;
+	Ok((next, view.fields_table(lua)?, Value::Nil))
+}
+
+/// Copies the fields of `value` into a table if it's a [`ProcessView`] or a
+/// [`WindowView`].
+fn view_fields<'lua>(lua: &'lua Lua, value: &Value<'lua>) -> mlua::Result<Option<Table<'lua>>> {
+	let Value::UserData(userdata) = value else {
+		return Ok(None);
+	};
+	if let Ok(process) = userdata.borrow::<ProcessView>() {
+		return process.fields_table(lua).map(Some);
+	}
+	if let Ok(window) = userdata.borrow::<WindowView>() {
+		return window.fields_table(lua).map(Some);
+	}
+	Ok(None)
+}
+
+/// The globals that [`install_table_compat`] replaces.
+pub(crate) const REPLACED_GLOBALS: &[&str] = &["type", "pairs"];
+
+/// Keeps scripts written when processes and windows were plain tables
+/// working: `type` reports them as tables, and `pairs` lists their fields.
+/// `typeof` still tells them apart, as `"Process"` and `"Window"`.
+pub(crate) fn install_table_compat(lua: &Lua) -> mlua::Result<()> {
+	let globals = lua.globals();
+	let type_key = lua.create_registry_value(globals.raw_get::<_, Function>("type")?)?;
+	globals.raw_set(
+		"type",
+		lua.create_function(move |lua, args: MultiValue| match args.iter().next() {
+			Some(Value::UserData(userdata))
+				if userdata.is::<ProcessView>() || userdata.is::<WindowView>() =>
+			{
+				"table".into_lua_multi(lua)
+			}
+			_ => lua.registry_value::<Function>(&type_key)?.call(args),
+		})?,
+	)?;
+	let pairs_key = lua.create_registry_value(globals.raw_get::<_, Function>("pairs")?)?;
+	globals.raw_set(
+		"pairs",
+		lua.create_function(move |lua, mut args: MultiValue<'_>| {
+			let fields = match args.iter().next() {
+				Some(value) => view_fields(lua, value)?,
+				None => None,
+			};
+			if let Some(fields) = fields {
+				args.pop_front();
+				args.push_front(Value::Table(fields));
+			}
+			lua.registry_value::<Function>(&pairs_key)?
+				.call::<_, MultiValue>(args)
+		})?,
+	)?;
+	Ok(())
+}
+
+impl Serialize for ProcessView {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		self.process().serialize(serializer)
+	}
+}
+
+impl Serialize for WindowView {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		self.window().serialize(serializer)
+	}
+}
+
+impl UserData for ProcessView {
+	fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+		fields.add_meta_field(MetaMethod::Type, "Process");
+	}
+
+	fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+		methods.add_meta_method(MetaMethod::Index, |lua, this, key: mlua::String| {
+			this.field(lua, key.as_bytes())
+		});
+		methods.add_meta_method(MetaMethod::Iter, |lua, this, ()| fields_iter(lua, this));
+		methods.add_meta_method(MetaMethod::NewIndex, |_, _, _: mlua::MultiValue| {
+			Err::<(), _>(mlua::Error::runtime("processes are read-only"))
+		});
+		methods.add_meta_method(MetaMethod::Eq, |_, this, other: AnyUserData| {
+			Ok(other
+				.borrow::<ProcessView>()
+				.is_ok_and(|other| other.process().pid == this.process().pid))
+		});
+		methods.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+			let process = this.process();
+			Ok(format!("Process({} [{}])", process.name, process.pid))
+		});
+	}
+}
+
+impl UserData for WindowView {
+	fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+		fields.add_meta_field(MetaMethod::Type, "Window");
+	}
+
+	fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+		methods.add_meta_method(MetaMethod::Index, |lua, this, key: mlua::String| {
+			this.field(lua, key.as_bytes())
+		});
+		methods.add_meta_method(MetaMethod::Iter, |lua, this, ()| fields_iter(lua, this));
+		methods.add_meta_method(MetaMethod::NewIndex, |_, _, _: mlua::MultiValue| {
+			Err::<(), _>(mlua::Error::runtime("windows are read-only"))
+		});
+		methods.add_meta_method(MetaMethod::Eq, |_, this, other: AnyUserData| {
+			Ok(other
+				.borrow::<WindowView>()
+				.is_ok_and(|other| other.window().hwnd == this.window().hwnd))
+		});
+		methods.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+			let window = this.window();
+			Ok(format!("Window({:?} [{:#x}])", window.title, window.hwnd))
+		});
+	}
+}
+
+/// Creates a Lua array of [`ProcessView`]s over `snapshot`, without copying
+/// any process or window data into the VM.
+pub fn process_list<'lua>(lua: &'lua Lua, snapshot: &Snapshot) -> mlua::Result<Table<'lua>> {
+	let list = lua.create_table_with_capacity(snapshot.len(), 0)?;
+	for index in 0..snapshot.len() {
+		list.raw_push(process_view(lua, snapshot, index)?)?;
+	}
+	Ok(list)
+}
+
+/// Creates a [`ProcessView`] of the process at `index` in `snapshot`.
+pub(crate) fn process_view<'lua>(
+	lua: &'lua Lua,
+	snapshot: &Snapshot,
+	index: usize,
+) -> mlua::Result<AnyUserData<'lua>> {
+	lua.create_ser_userdata(ProcessView {
+		snapshot: snapshot.clone(),
+		index,
+	})
+}
+
+/// Creates a [`WindowView`] of a window that isn't in the current snapshot,
+/// such as the captured window, whose title may have changed since it was
+/// selected.
+pub(crate) fn detached_window_view(lua: &Lua, window: Window) -> mlua::Result<AnyUserData<'_>> {
+	let snapshot = Arc::new(vec![Process {
+		windows: vec![window],
+		..Process::default()
+	}]);
+	window_view(lua, &snapshot, 0, 0)
+}
+
+/// Creates a [`WindowView`] of the window at `window` in the process at
+/// `process` in `snapshot`.
+pub(crate) fn window_view<'lua>(
+	lua: &'lua Lua,
+	snapshot: &Snapshot,
+	process: usize,
+	window: usize,
+) -> mlua::Result<AnyUserData<'lua>> {
+	lua.create_ser_userdata(WindowView {
+		snapshot: snapshot.clone(),
+		process,
+		slot: WindowSlot::Listed(window),
+	})
+}
@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: MPL-2.0
+use super::snapshot::{process_view, window_view, ProcessView, Snapshot};
+use crate::window::{Process, Window};
+use mlua::{Lua, LuaSerdeExt, Table, Value};
+use serde::Deserialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The delay requested by the last call to `wincat.set_next_check`.
+pub(crate) struct NextCheck(pub f32);
+
+/// The snapshot the running `select_window` call was given. The helpers
+/// search it rather than the latest one, so that the windows they find are
+/// in the process list the returned window is checked against.
+pub(crate) struct CallSnapshot(pub Snapshot);
+
+/// Gets the snapshot the helpers search: the one given to the running
+/// `select_window` call, or the latest one outside of it.
+fn snapshot(lua: &Lua) -> Snapshot {
+	match lua.app_data_ref::<CallSnapshot>() {
+		Some(snapshot) => snapshot.0.clone(),
+		None => crate::module::window::snapshot(),
+	}
+}
+
+/// Criteria for `wincat.find_windows`. Every criterion that is set must match.
+#[derive(Debug, Default, Deserialize)]
+struct WindowFilter {
+	/// The owning process's executable name, case-insensitive, `.exe`
+	/// optional.
+	exe: Option<String>,
+	/// A case-insensitive substring of the window title.
+	title: Option<String>,
+	/// The window class name, case-insensitive.
+	class: Option<String>,
+	visible: Option<bool>,
+	min_width: Option<i32>,
+	min_height: Option<i32>,
+}
+
+impl WindowFilter {
+	fn matches(&self, process: &Process, window: &Window) -> bool {
+		self.exe
+			.as_deref()
+			.is_none_or(|exe| process_name_matches(&process.name, exe))
+			&& self
+				.title
+				.as_deref()
+				.is_none_or(|query| window.title.to_lowercase().contains(&query.to_lowercase()))
+			&& self
+				.class
+				.as_deref()
+				.is_none_or(|class| window.class_name.eq_ignore_ascii_case(class))
+			&& self.visible.is_none_or(|visible| window.visible == visible)
+			&& self.min_width.is_none_or(|width| window.width >= width)
+			&& self.min_height.is_none_or(|height| window.height >= height)
+	}
+}
+
+/// Installs the `wincat` helper library into `lua`'s globals.
+pub(crate) fn install(lua: &Lua) -> mlua::Result<()> {
+	let wincat = lua.create_table()?;
+	wincat.set("find_process", lua.create_function(find_process)?)?;
+	wincat.set("find_windows", lua.create_function(find_windows)?)?;
+	wincat.set("largest_window", lua.create_function(largest_window)?)?;
+	wincat.set("regex", lua.create_function(super::pattern::compile)?)?;
+	wincat.set("log", super::logging::create_table(lua)?)?;
+	wincat.set(
+		"now",
+		lua.create_function(|_, ()| {
+			Ok(SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0.0, |now| now.as_secs_f64()))
+		})?,
+	)?;
+	wincat.set(
+		"uptime",
+		lua.create_function(|_, ()| Ok(crate::module::LOADED_AT.elapsed().as_secs_f64()))?,
+	)?;
+	wincat.set("set_next_check", lua.create_function(set_next_check)?)?;
+	lua.globals().set("wincat", wincat)
+}
+
+/// Compares a process name against a query, ignoring case and an optional
+/// `.exe` extension on either side.
+fn process_name_matches(name: &str, query: &str) -> bool {
+	fn strip_exe(name: &str) -> &str {
+		match name.len().checked_sub(4) {
+			Some(split)
+				if name.is_char_boundary(split) && name[split..].eq_ignore_ascii_case(".exe") =>
+			{
+				&name[..split]
+			}
+			_ => name,
+		}
+	}
+	strip_exe(name).eq_ignore_ascii_case(strip_exe(query))
+}
+
+fn find_process<'lua>(lua: &'lua Lua, name: String) -> mlua::Result<Value<'lua>> {
+	let processes = snapshot(lua);
+	match processes
+		.iter()
+		.position(|process| process_name_matches(&process.name, &name))
+	{
+		Some(index) => process_view(lua, &processes, index).map(Value::UserData),
+		None => Ok(Value::Nil),
+	}
+}
+
+fn find_windows<'lua>(lua: &'lua Lua, filter: Value<'lua>) -> mlua::Result<Table<'lua>> {
+	let filter = match filter {
+		Value::Nil => WindowFilter::default(),
+		filter => lua.from_value::<WindowFilter>(filter)?,
+	};
+	let processes = snapshot(lua);
+	let windows = lua.create_table()?;
+	for (process_index, process) in processes.iter().enumerate() {
+		for (window_index, window) in process.windows.iter().enumerate() {
+			if filter.matches(process, window) {
+				windows.raw_push(window_view(lua, &processes, process_index, window_index)?)?;
+			}
+		}
+	}
+	Ok(windows)
+}
+
+/// Finds the visible window with the largest area belonging to a process,
+/// given either the process or its pid.
+fn largest_window<'lua>(lua: &'lua Lua, process: Value<'lua>) -> mlua::Result<Value<'lua>> {
+	let pid = match &process {
+		Value::UserData(userdata) => userdata.borrow::<ProcessView>()?.process().pid,
+		Value::Table(table) => table.get::<_, u32>("pid")?,
+		other => lua.from_value::<u32>(other.clone())?,
+	};
+	let processes = snapshot(lua);
+	let largest = processes
+		.iter()
+		.enumerate()
+		.find(|(_, process)| process.pid == pid)
+		.and_then(|(process_index, process)| {
+			process
+				.windows
+				.iter()
+				.enumerate()
+				.filter(|(_, window)| window.visible)
+				.max_by_key(|(_, window)| window.width as i64 * window.height as i64)
+				.map(|(window_index, _)| (process_index, window_index))
+		});
+	match largest {
+		Some((process, window)) => {
+			window_view(lua, &processes, process, window).map(Value::UserData)
+		}
+		None => Ok(Value::Nil),
+	}
+}
+
+/// Asks for the selector to next be evaluated in `seconds`, instead of after
+/// the source's default interval.
+fn set_next_check(lua: &Lua, seconds: f64) -> mlua::Result<()> {
+	let seconds = crate::selection::clamp_next_check(seconds).map_err(|err| {
+		mlua::Error::runtime(format!(
+			"{}{}",
+			super::caller_location(lua).unwrap_or_default(),
+			err
+		))
+	})?;
+	lua.set_app_data(NextCheck(seconds));
+	Ok(())
+}
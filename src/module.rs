@@ -1,14 +1,22 @@
 // SPDX-License-Identifier: MPL-2.0
 pub mod window;
 
-use std::sync::atomic::Ordering;
+use std::{ffi::CStr, path::PathBuf, sync::atomic::Ordering, time::Instant};
 
 use obs_wrapper::{
 	log::Logger,
 	module::{LoadContext, Module, ModuleContext},
+	obs_sys::{bfree, obs_module_get_config_path},
 	source::Icon,
 	string::ObsString,
 };
+use once_cell::sync::{Lazy, OnceCell};
+
+/// The directory shared selector modules are loaded from, inside the module's
+/// config directory.
+pub static SCRIPTS_DIR: OnceCell<PathBuf> = OnceCell::new();
+/// When the module was loaded, for `wincat.uptime()`.
+pub static LOADED_AT: Lazy<Instant> = Lazy::new(Instant::now);
 
 #[cfg_attr(target_pointer_width = "32", repr(align(64)))]
 #[cfg_attr(target_pointer_width = "64", repr(align(128)))]
@@ -27,6 +35,17 @@ fn get_ctx(&self) -> &ModuleContext {
 
 	fn load(&mut self, load_context: &mut LoadContext) -> bool {
 		let _ = Logger::new().init();
+		Lazy::force(&LOADED_AT);
+		if let Some(dir) = self.config_path(obs_string!("scripts")) {
+			if let Err(err) = std::fs::create_dir_all(&dir) {
+				warn!(
+					"failed to create scripts directory {}: {}",
+					dir.display(),
+					err
+				);
+			}
+			let _ = SCRIPTS_DIR.set(dir);
+		}
 		let source = load_context
 			.create_source_builder::<crate::source::WincatSource>()
 			.with_icon(Icon::WindowCapture)
@@ -62,4 +81,18 @@ fn author() -> ObsString {
 	}
 }
 
+impl WincatModule {
+	fn config_path(&self, file: ObsString) -> Option<PathBuf> {
+		unsafe {
+			let ptr = obs_module_get_config_path(self.context.get_raw(), file.as_ptr());
+			if ptr.is_null() {
+				return None;
+			}
+			let path = PathBuf::from(CStr::from_ptr(ptr).to_string_lossy().into_owned());
+			bfree(ptr.cast());
+			Some(path)
+		}
+	}
+}
+
 obs_register_module!(WincatModule);
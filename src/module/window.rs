@@ -1,16 +1,61 @@
 // SPDX-License-Identifier: MPL-2.0
-use crate::window::{get_processes, Process};
+#[cfg(windows)]
+use crate::window::Win32Provider;
+#[cfg(target_os = "linux")]
+use crate::window::{ProcfsProvider, X11Provider};
+use crate::{
+	lua::Snapshot,
+	window::{Process, WindowProvider},
+};
 use ahash::AHashMap;
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
 use std::{
-	sync::atomic::{AtomicBool, Ordering},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
 	time::Duration,
 };
 
-pub static PROCESS_LIST: Lazy<RwLock<Vec<Process>>> =
-	Lazy::new(|| RwLock::new(Vec::with_capacity(128)));
+/// The latest process list. Each poll publishes a new snapshot rather than
+/// updating the old one, so readers can hold on to it without blocking.
+pub static PROCESS_LIST: Lazy<RwLock<Snapshot>> = Lazy::new(|| RwLock::new(Arc::default()));
 pub static SHOULD_RUN: AtomicBool = AtomicBool::new(false);
+/// Where processes and windows are read from.
+static PROVIDER: Lazy<RwLock<Arc<dyn WindowProvider>>> =
+	Lazy::new(|| RwLock::new(default_provider()));
+
+/// Picks the provider for the platform we're running on. On Linux, windows
+/// are read from the X server if there is one.
+fn default_provider() -> Arc<dyn WindowProvider> {
+	#[cfg(windows)]
+	return Arc::new(Win32Provider);
+	#[cfg(target_os = "linux")]
+	{
+		if std::env::var_os("DISPLAY").is_some() {
+			match X11Provider::connect(None) {
+				Ok(provider) => return Arc::new(provider),
+				Err(err) => warn!("not reading windows from X11: {:#}", err),
+			}
+		}
+		Arc::new(ProcfsProvider::new())
+	}
+	#[cfg(not(any(windows, target_os = "linux")))]
+	return Arc::new(crate::window::FakeProvider::default());
+}
+
+/// Gets the provider processes and windows are currently read from.
+pub fn provider() -> Arc<dyn WindowProvider> {
+	PROVIDER.read().clone()
+}
+
+/// Replaces the provider processes and windows are read from, such as with a
+/// [`FakeProvider`](crate::window::FakeProvider) in tests. The next poll
+/// publishes a snapshot from it.
+pub fn set_provider(provider: impl WindowProvider + 'static) {
+	*PROVIDER.write() = Arc::new(provider);
+}
 
 #[inline(never)]
 pub(crate) fn process_loading_thread() {
@@ -22,13 +67,22 @@ pub(crate) fn process_loading_thread() {
 	};
 	std::thread::sleep(Duration::from_secs(1));
 	let mut process_map = AHashMap::<u32, Process>::with_capacity(128);
-	let mut secondary_buffer = Vec::<Process>::with_capacity(128);
 	while SHOULD_RUN.load(Ordering::SeqCst) {
-		process_map.clear();
-		secondary_buffer.clear();
-		get_processes(&mut process_map);
-		secondary_buffer.extend(process_map.drain().map(|(_, v)| v));
-		std::mem::swap(&mut *PROCESS_LIST.write(), &mut secondary_buffer);
+		refresh_processes(&mut process_map);
 		std::thread::sleep(Duration::from_secs(1));
 	}
 }
+
+/// Takes a new snapshot from the current provider and publishes it, using
+/// `process_map` as scratch space.
+pub fn refresh_processes(process_map: &mut AHashMap<u32, Process>) {
+	process_map.clear();
+	provider().processes(process_map);
+	let processes = process_map.drain().map(|(_, v)| v).collect::<Vec<_>>();
+	*PROCESS_LIST.write() = Arc::new(processes);
+}
+
+/// Gets the latest process list snapshot.
+pub fn snapshot() -> Snapshot {
+	PROCESS_LIST.read().clone()
+}
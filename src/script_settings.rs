@@ -0,0 +1,268 @@
+// SPDX-License-Identifier: MPL-2.0
+use ahash::AHashSet;
+use anyhow::{bail, ensure, Context, Result};
+use mlua::{IntoLua, Lua, Table, Value};
+use obs_wrapper::{
+	data::DataObj,
+	properties::{BoolProp, NumberProp, Properties, TextProp, TextType},
+	string::ObsString,
+};
+
+/// The prefix of the source settings keys that script settings are stored
+/// under, so they can't collide with the source's own settings.
+const KEY_PREFIX: &str = "script_settings.";
+
+/// The settings a script declared in its `settings` table, in the order they
+/// should be shown.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct SettingsSchema(pub Vec<ScriptSetting>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ScriptSetting {
+	name: String,
+	label: String,
+	kind: SettingKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SettingKind {
+	String {
+		default: String,
+	},
+	Bool {
+		default: bool,
+	},
+	Int {
+		default: i64,
+		min: i64,
+		max: i64,
+	},
+	/// A drop-down of `(label, value)` options.
+	List {
+		options: Vec<(String, String)>,
+		default: String,
+	},
+}
+
+/// The current value of a script setting.
+#[derive(Debug, Clone)]
+pub(crate) enum SettingValue {
+	String(String),
+	Bool(bool),
+	Int(i64),
+}
+
+impl<'lua> IntoLua<'lua> for &SettingValue {
+	fn into_lua(self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+		match self {
+			SettingValue::String(value) => value.as_str().into_lua(lua),
+			SettingValue::Bool(value) => value.into_lua(lua),
+			SettingValue::Int(value) => value.into_lua(lua),
+		}
+	}
+}
+
+impl SettingsSchema {
+	/// Parses a script's `settings` table, an array of
+	/// `{ name = ..., type = ..., label = ..., default = ... }` tables.
+	pub(crate) fn parse(table: Table) -> Result<Self> {
+		let mut settings = Vec::new();
+		let mut names = AHashSet::new();
+		for (index, entry) in table.sequence_values::<Table>().enumerate() {
+			let setting = entry
+				.context("must be a table")
+				.and_then(ScriptSetting::parse)
+				.with_context(|| format!("invalid setting #{}", index + 1))?;
+			ensure!(
+				names.insert(setting.name.clone()),
+				"duplicate setting '{}'",
+				setting.name
+			);
+			settings.push(setting);
+		}
+		Ok(Self(settings))
+	}
+
+	/// Adds a property for each setting to `props`.
+	pub(crate) fn add_properties(&self, props: &mut Properties) {
+		for setting in &self.0 {
+			let key = setting.key();
+			let label = ObsString::from(setting.label.as_str());
+			match &setting.kind {
+				SettingKind::String { .. } => {
+					props.add(key, label, TextProp::new(TextType::Default));
+				}
+				SettingKind::Bool { .. } => {
+					props.add(key, label, BoolProp);
+				}
+				SettingKind::Int { min, max, .. } => {
+					props.add(
+						key,
+						label,
+						NumberProp::<i64>::new_int().with_range(*min..=*max),
+					);
+				}
+				SettingKind::List { options, .. } => {
+					let mut list = props.add_list::<ObsString>(key, label, false);
+					for (label, value) in options {
+						list.push(label.as_str(), ObsString::from(value.as_str()));
+					}
+				}
+			}
+		}
+	}
+
+	/// Registers each setting's default value with OBS.
+	pub(crate) fn set_defaults(&self, settings: &mut DataObj) {
+		for setting in &self.0 {
+			let key = setting.key();
+			match &setting.kind {
+				SettingKind::String { default } | SettingKind::List { default, .. } => {
+					settings.set_default::<ObsString>(key, default.as_str())
+				}
+				SettingKind::Bool { default } => settings.set_default::<bool>(key, *default),
+				SettingKind::Int { default, .. } => settings.set_default::<i64>(key, *default),
+			}
+		}
+	}
+
+	/// Reads the current value of each setting, falling back to its default.
+	pub(crate) fn values(&self, settings: &DataObj) -> Vec<(String, SettingValue)> {
+		self.0
+			.iter()
+			.map(|setting| {
+				let key = setting.key();
+				let value = match &setting.kind {
+					SettingKind::String { default } | SettingKind::List { default, .. } => {
+						SettingValue::String(
+							settings
+								.get::<ObsString>(key)
+								.map_or_else(|| default.clone(), |value| value.as_str().to_owned()),
+						)
+					}
+					SettingKind::Bool { default } => {
+						SettingValue::Bool(settings.get::<bool>(key).unwrap_or(*default))
+					}
+					SettingKind::Int { default, min, max } => SettingValue::Int(
+						settings
+							.get::<i64>(key)
+							.unwrap_or(*default)
+							.clamp(*min, *max),
+					),
+				};
+				(setting.name.clone(), value)
+			})
+			.collect()
+	}
+}
+
+impl ScriptSetting {
+	fn parse(entry: Table) -> Result<Self> {
+		let name = entry
+			.get::<_, String>("name")
+			.context("name must be a string")?;
+		ensure!(
+			!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+			"name '{name}' may only contain letters, digits and underscores"
+		);
+		let label = entry
+			.get::<_, Option<String>>("label")
+			.context("label must be a string")?
+			.unwrap_or_else(|| name.clone());
+		let kind = match entry
+			.get::<_, String>("type")
+			.context("type must be a string")?
+			.as_str()
+		{
+			"string" => SettingKind::String {
+				default: entry
+					.get::<_, Option<String>>("default")
+					.context("default must be a string")?
+					.unwrap_or_default(),
+			},
+			"bool" => SettingKind::Bool {
+				default: entry
+					.get::<_, Option<bool>>("default")
+					.context("default must be a boolean")?
+					.unwrap_or(false),
+			},
+			"int" => {
+				let min = int_field(&entry, &name, "min")?.unwrap_or(i32::MIN as i64);
+				let max = int_field(&entry, &name, "max")?.unwrap_or(i32::MAX as i64);
+				ensure!(min <= max, "min must not be greater than max");
+				SettingKind::Int {
+					default: int_field(&entry, &name, "default")?
+						.unwrap_or(0)
+						.clamp(min, max),
+					min,
+					max,
+				}
+			}
+			"list" => {
+				let options = entry
+					.get::<_, Table>("options")
+					.context("options must be an array")?
+					.sequence_values::<Value>()
+					.map(|option| match option? {
+						Value::String(value) => {
+							let value = value.to_str()?.to_owned();
+							Ok((value.clone(), value))
+						}
+						Value::Table(option) => {
+							let value = option
+								.get::<_, String>("value")
+								.context("option value must be a string")?;
+							let label = option
+								.get::<_, Option<String>>("label")
+								.context("option label must be a string")?
+								.unwrap_or_else(|| value.clone());
+							Ok((label, value))
+						}
+						other => bail!(
+							"options must be strings or {{ label, value }} tables, not {}",
+							other.type_name()
+						),
+					})
+					.collect::<Result<Vec<_>>>()?;
+				ensure!(!options.is_empty(), "options must not be empty");
+				let default = match entry
+					.get::<_, Option<String>>("default")
+					.context("default must be a string")?
+				{
+					Some(default) => {
+						ensure!(
+							options.iter().any(|(_, value)| *value == default),
+							"default '{default}' is not one of the options"
+						);
+						default
+					}
+					None => options[0].1.clone(),
+				};
+				SettingKind::List { options, default }
+			}
+			other => bail!("unknown type '{other}'; expected string, bool, int or list"),
+		};
+		Ok(Self { name, label, kind })
+	}
+
+	fn key(&self) -> ObsString {
+		ObsString::from(format!("{KEY_PREFIX}{}", self.name))
+	}
+}
+
+/// Reads an optional integer field of an `int` setting. OBS stores integer
+/// properties as a C `int`, so values outside the `i32` range are rejected.
+fn int_field(entry: &Table, name: &str, field: &str) -> Result<Option<i64>> {
+	let value = entry
+		.get::<_, Option<i64>>(field)
+		.with_context(|| format!("{field} of setting '{name}' must be an integer"))?;
+	if let Some(value) = value {
+		ensure!(
+			i32::try_from(value).is_ok(),
+			"{field} of setting '{name}' must be between {} and {}, not {value}",
+			i32::MIN,
+			i32::MAX
+		);
+	}
+	Ok(value)
+}
@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: MPL-2.0
+use crate::window::{Process, Window, WindowProvider};
+use anyhow::{bail, ensure, Result};
+use serde::Deserialize;
+
+/// The shortest delay a script may ask for before it's next evaluated.
+const MIN_NEXT_CHECK: f64 = 0.1;
+/// The longest delay a script may ask for before it's next evaluated.
+const MAX_NEXT_CHECK: f64 = 3600.0;
+
+/// Checks a delay a script asked for before it's next evaluated, either with
+/// `wincat.set_next_check` or a candidate's `next_check`, and clamps it to a
+/// sane range, so the title isn't checked every frame, or never again.
+pub fn clamp_next_check(seconds: f64) -> Result<f32> {
+	ensure!(
+		seconds.is_finite(),
+		"next check must be a finite number of seconds"
+	);
+	Ok(seconds.clamp(MIN_NEXT_CHECK, MAX_NEXT_CHECK) as f32)
+}
+
+/// Capture options a script can override for a single selection. Unset
+/// options fall back to the source's settings.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub struct CaptureOptions {
+	pub cursor: Option<bool>,
+	pub client_area: Option<bool>,
+	pub force_sdr: Option<bool>,
+}
+
+/// A window chosen by the selector script, checked against the current
+/// process list, along with any capture options it returned with it.
+#[derive(Debug, Clone)]
+pub struct Selection {
+	pub window: Window,
+	/// The name of the process that owns the window.
+	pub process: String,
+	pub options: CaptureOptions,
+	/// Seconds until the window's title should next be checked.
+	pub next_check: Option<f32>,
+}
+
+/// The way a script refers to a window: a bare hwnd, or any table with an
+/// `hwnd` field, such as a `Window` from the process list.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum WindowRef {
+	Hwnd(isize),
+	Table { hwnd: isize },
+}
+
+impl WindowRef {
+	pub fn hwnd(self) -> isize {
+		match self {
+			Self::Hwnd(hwnd) | Self::Table { hwnd } => hwnd,
+		}
+	}
+}
+
+/// A single window returned by the selector script: either a [`WindowRef`],
+/// or a table of the form `{ window = w, cursor = false, ... }`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum Candidate {
+	WithOptions {
+		window: WindowRef,
+		#[serde(default)]
+		next_check: Option<f32>,
+		#[serde(flatten)]
+		options: CaptureOptions,
+	},
+	Window(WindowRef),
+}
+
+impl Candidate {
+	/// Looks up the candidate's window in `processes`, rejecting windows that
+	/// aren't there or that `provider` says now belong to a different process.
+	pub fn resolve(
+		self,
+		processes: &[Process],
+		provider: &dyn WindowProvider,
+	) -> Result<Selection> {
+		let (window, options, next_check) = match self {
+			Self::WithOptions {
+				window,
+				options,
+				next_check,
+			} => (window, options, next_check),
+			Self::Window(window) => (window, CaptureOptions::default(), None),
+		};
+		let next_check = next_check
+			.map(|seconds| clamp_next_check(seconds as f64))
+			.transpose()?;
+		let hwnd = window.hwnd();
+		let (process, window) = match processes.iter().find_map(|process| {
+			process
+				.windows
+				.iter()
+				.find(|window| window.hwnd == hwnd)
+				.map(|window| (process, window))
+		}) {
+			Some(found) => found,
+			None => bail!(
+				"window {:#x} is not in the current process list; it may have closed, or never \
+				 existed",
+				hwnd
+			),
+		};
+		match provider.window_pid(hwnd) {
+			Some(pid) if pid == process.pid => Ok(Selection {
+				window: window.clone(),
+				process: process.name.clone(),
+				options,
+				next_check,
+			}),
+			Some(pid) => bail!(
+				"window {:#x} belonged to {} (pid {}), but now belongs to pid {}",
+				hwnd,
+				process.name,
+				process.pid,
+				pid
+			),
+			None => bail!(
+				"window {:#x} ({:?}) of {} no longer exists",
+				hwnd,
+				window.title,
+				process.name
+			),
+		}
+	}
+}
+
+/// Checks whether the captured `window` has been retitled, returning its new
+/// title if so. Windows that have lost their title, usually because they've
+/// closed, are left to the capture going inactive.
+pub fn title_change(window: &Window, provider: &dyn WindowProvider) -> Option<String> {
+	let title = provider.window_title(window.hwnd);
+	(!title.is_empty() && title != window.title).then_some(title)
+}
+
+/// Everything `select_window` may return: a single candidate, or an ordered
+/// array of candidates to fall back through.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum SelectionValue {
+	Many(Vec<Candidate>),
+	One(Candidate),
+}
+
+impl SelectionValue {
+	/// Gets the selected candidates, in order of preference.
+	pub fn into_candidates(self) -> Vec<Candidate> {
+		match self {
+			Self::Many(candidates) => candidates,
+			Self::One(candidate) => vec![candidate],
+		}
+	}
+}
@@ -1,10 +1,21 @@
 // SPDX-License-Identifier: MPL-2.0
-use crate::{capture::WinrtCapture, window::Window};
-use anyhow::{Context, Result};
-use mlua::{Function, Lua, LuaSerdeExt, Value};
+use crate::{
+	capture::{Capture, CaptureConfig},
+	lua::{ScriptOutOfMemory, ScriptTimeout},
+	script_settings::{SettingValue, SettingsSchema},
+	selection::{self, Selection, SelectionValue},
+	window::Window,
+};
+use anyhow::{bail, Context, Result};
+use mlua::{Function, IntoLuaMulti, Lua, LuaSerdeExt, Table, Value};
 use obs_wrapper::{
 	data::DataObj,
-	properties::{BoolProp, Properties, TextProp, TextType},
+	obs_sys::{
+		obs_data_set_string, obs_properties_add_button, obs_properties_add_text, obs_properties_t,
+		obs_property_t, obs_source_get_name, obs_source_get_private_settings,
+		obs_source_get_settings, obs_source_t, obs_source_update_properties, obs_text_type,
+	},
+	properties::{BoolProp, NumberProp, PathProp, PathType, Properties, TextProp, TextType},
 	source::{
 		ActivateSource, CreatableSourceContext, DeactivateSource, GetDefaultsSource,
 		GetHeightSource, GetNameSource, GetPropertiesSource, GetWidthSource, GlobalContext,
@@ -12,69 +23,747 @@
 		VideoTickSource,
 	},
 	string::ObsString,
+	wrapper::PtrWrapper,
+};
+use parking_lot::Mutex;
+use std::{
+	collections::hash_map::DefaultHasher,
+	ffi::{c_void, CStr},
+	hash::{Hash, Hasher},
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use windows::Win32::Foundation::HWND;
+
+/// The longest a source will wait before retrying a script that timed out.
+const MAX_BACKOFF: f32 = 60.0;
+const DEFAULT_TIME_LIMIT_MS: u32 = 50;
+const DEFAULT_MEMORY_LIMIT_MB: u32 = 16;
+const DEFAULT_RETRY_INTERVAL: f64 = 2.0;
+const DEFAULT_TITLE_CHECK_INTERVAL: f64 = 5.0;
+/// The private settings key the script's persisted `state` table is kept under.
+const STATE_KEY: &str = "script_state";
+/// How often the script file is checked for changes, in seconds.
+const SCRIPT_FILE_POLL_INTERVAL: f32 = 1.0;
 
 struct CaptureSession {
-	hwnd: HWND,
-	title: String,
-	capture: WinrtCapture,
+	window: Window,
+	/// The name of the process that owns the window.
+	process: String,
+	/// The 1-based position of the window among the script's candidates.
+	candidate: usize,
+	capture: Box<dyn Capture>,
 }
 
 #[cfg_attr(target_pointer_width = "32", repr(align(64)))]
 #[cfg_attr(target_pointer_width = "64", repr(align(128)))]
 pub struct WincatSource {
+	/// The source this data belongs to. It isn't referenced, since OBS keeps
+	/// the source alive for as long as its data, and a reference held here
+	/// would keep the source from ever being destroyed.
+	source: *mut obs_source_t,
 	lua: Lua,
 	settings: Settings,
 	ticks: f32,
+	/// Seconds until the next re-evaluation, as requested by the script.
+	next_check: Option<f32>,
+	backoff: f32,
+	cooldown: f32,
+	script_poll_ticks: f32,
+	script_modified: Option<SystemTime>,
+	/// The [`WincatSource::script_hash`] of the loaded selector.
+	loaded_script: Option<u64>,
+	persisted_state: Option<String>,
+	/// Whether the last attempt to persist the script's state failed.
+	persist_failed: bool,
+	/// The current values of the settings declared by the script.
+	script_values: Vec<(String, SettingValue)>,
 	capture: Option<CaptureSession>,
+	/// What the properties show about the script and the capture. They are
+	/// built on the UI thread, so only see what the graphics thread
+	/// publishes here, rather than the VM or the capture themselves.
+	status: Arc<Mutex<Status>>,
+}
+
+/// What [`WincatSource::load_fresh`] does with the VM it replaces.
+#[derive(Clone, Copy)]
+enum Reload {
+	/// Carry the script's state over, keeping the old VM if loading fails.
+	KeepOnFailure,
+	/// Carry the script's state over, replacing the old VM even if loading
+	/// fails.
+	Replace,
+	/// Replace the old VM without reading anything from it, dropping the
+	/// script's state along with it.
+	Discard,
+}
+
+#[derive(Default)]
+struct Status {
+	last_error: Option<ScriptFailure>,
+	capture: Option<CaptureStatus>,
+	/// The settings declared by the loaded script.
+	schema: SettingsSchema,
 }
 
-#[link(name = "obs")]
-extern "C" {
-	fn obs_enter_graphics();
-	fn obs_leave_graphics();
+/// The last error a script ran into, shown in the source's properties.
+struct ScriptFailure {
+	/// The error and its causes, including any Lua traceback.
+	message: String,
+	at: SystemTime,
+}
+
+/// The window being captured, as of the last video tick.
+struct CaptureStatus {
+	title: String,
+	process: String,
+	hwnd: isize,
+	candidate: usize,
+	width: u32,
+	height: u32,
+	active: bool,
 }
 
 impl WincatSource {
 	pub fn run_callbacks(&mut self) -> Result<()> {
-		unsafe { obs_enter_graphics() };
-		scopeguard::defer! { unsafe { obs_leave_graphics(); } };
-		let handler: Function = self
+		self.drop_capture("reselect");
+		// With no selector loaded, because the script is empty or failed to
+		// load, there is nothing to select, and no new error to report over
+		// the one that loading recorded.
+		let handler: Function = match self
 			.lua
-			.named_registry_value("select_window")
-			.context("failed to get select_window")?;
-		let procs = self
-			.lua
-			.to_value(&*crate::module::window::PROCESS_LIST.read())
-			.expect("failed to serialize processes");
-		std::mem::drop(self.capture.take());
-		let window = match handler
-			.call::<_, Option<Value>>(procs)
-			.context("failed to call select_window")?
+			.named_registry_value::<Option<Function>>("select_window")
+			.context("failed to get select_window")?
 		{
-			Some(window_value) => self
-				.lua
-				.from_value::<Window>(window_value)
-				.context("failed to deserialize window")?,
+			Some(handler) => handler,
 			None => {
-				debug!("selecting window: None");
+				debug!("selecting window: no selector loaded");
 				return Ok(());
 			}
 		};
-		self.capture = WinrtCapture::new(
-			self.settings.cursor,
-			window.hwnd,
-			self.settings.client_area,
-			self.settings.force_sdr,
-		)
-		.map(|capture| CaptureSession {
-			hwnd: HWND(window.hwnd),
-			title: window.title,
-			capture,
-		});
+		let snapshot = crate::module::window::snapshot();
+		let procs = crate::lua::process_list(&self.lua, &snapshot)
+			.map_err(|err| crate::lua::script_error(&self.lua, err))
+			.context("failed to create process list")?;
+		let state = script_state(&self.lua, || {
+			self.settings
+				.persist_state
+				.then(|| private_settings(self.source).get::<ObsString>(STATE_KEY))
+				.flatten()
+		})
+		.context("failed to get script state")?;
+		let script_settings = self
+			.lua
+			.create_table_from(
+				self.script_values
+					.iter()
+					.map(|(name, value)| (name.as_str(), value)),
+			)
+			.context("failed to create script settings")?;
+		let budget = self.settings.time_limit();
+		crate::lua::take_next_check(&self.lua);
+		let result = crate::lua::with_call_snapshot(&self.lua, &snapshot, || {
+			crate::lua::call_with_budget::<_, Option<Value>>(
+				&self.lua,
+				&handler,
+				(procs, state.clone(), script_settings),
+				budget,
+			)
+		})
+		.context("failed to call select_window");
+		self.next_check = crate::lua::take_next_check(&self.lua);
+		self.ticks = 0.0;
+		if self.settings.persist_state {
+			match state_to_json(&self.lua, &state) {
+				Ok(json) => {
+					self.persist_failed = false;
+					if self.persisted_state.as_deref() != Some(json.as_str()) {
+						set_private_string(self.source, STATE_KEY, &json);
+						self.persisted_state = Some(json);
+					}
+				}
+				// Only warn when persisting starts failing, not on every call.
+				Err(err) if !self.persist_failed => {
+					warn!("failed to persist script state: {:#}", err);
+					self.persist_failed = true;
+				}
+				Err(_) => {}
+			}
+		}
+		let candidates = match result? {
+			Some(value) => match self.lua.from_value::<SelectionValue>(value.clone()) {
+				Ok(selection) => selection.into_candidates(),
+				Err(_) => bail!(
+					"select_window{} returned {}, but must return a window, an hwnd, a {{ window \
+					 = ... }} table, or an array of them",
+					crate::lua::function_location(&handler)
+						.map(|location| format!(" (defined at {location})"))
+						.unwrap_or_default(),
+					crate::lua::describe_value(&value)
+				),
+			},
+			None => Vec::new(),
+		};
+		if candidates.is_empty() {
+			debug!("selecting window: None");
+			return Ok(());
+		}
+		let count = candidates.len();
+		let provider = crate::module::window::provider();
+		let selections = candidates
+			.into_iter()
+			.map(|candidate| candidate.resolve(&snapshot, &*provider))
+			.collect::<Vec<_>>();
+		for (index, selection) in selections.into_iter().enumerate() {
+			let Selection {
+				window,
+				process,
+				options,
+				next_check,
+			} = match selection {
+				Ok(selection) => selection,
+				Err(err) if count == 1 => {
+					return Err(err.context("select_window returned an invalid window"))
+				}
+				Err(err) => {
+					warn!("rejected candidate {} of {}: {}", index + 1, count, err);
+					continue;
+				}
+			};
+			let capture = provider.capture(window.hwnd, CaptureConfig {
+				cursor: options.cursor.unwrap_or(self.settings.cursor),
+				client_area: options.client_area.unwrap_or(self.settings.client_area),
+				force_sdr: options.force_sdr.unwrap_or(self.settings.force_sdr),
+			});
+			match capture {
+				Some(capture) => {
+					if index > 0 {
+						info!("capturing candidate {} of {}", index + 1, count);
+					}
+					self.capture = Some(CaptureSession {
+						window,
+						process,
+						candidate: index + 1,
+						capture,
+					});
+					self.next_check = next_check.or(self.next_check);
+					break;
+				}
+				None => warn!(
+					"failed to capture candidate {} of {} ({:?}): {}",
+					index + 1,
+					count,
+					window.title,
+					if provider.is_window(window.hwnd) {
+						"capture could not be initialized; the window may be elevated or protected"
+					} else {
+						"the window no longer exists"
+					}
+				),
+			}
+		}
+		if let Some(session) = &self.capture {
+			debug!(
+				"capturing {:?} (candidate {})",
+				session.window.title, session.candidate
+			);
+			if let Ok(window) = crate::lua::detached_window_view(&self.lua, session.window.clone())
+			{
+				call_hook(&self.lua, budget, "on_capture_started", window)
+					.context("failed to call on_capture_started")?;
+			}
+		}
 		Ok(())
 	}
+
+	/// Stops the current capture, if any, telling the script why.
+	fn drop_capture(&mut self, reason: &str) {
+		let session = match self.capture.take() {
+			Some(session) => session,
+			None => return,
+		};
+		std::mem::drop(session.capture);
+		let result = match crate::lua::detached_window_view(&self.lua, session.window.clone()) {
+			Ok(window) => call_hook(
+				&self.lua,
+				self.settings.time_limit(),
+				"on_capture_lost",
+				(window, reason),
+			)
+			.map(drop),
+			Err(_) => Ok(()),
+		};
+		if let Err(err) = result {
+			self.script_failed(err, "failed to call on_capture_lost");
+		}
+	}
+
+	/// Lets the script react to the captured window's title changing to
+	/// `title`, returning whether a new window should be selected. The
+	/// script vetoes re-selection by returning `false` from
+	/// `on_title_changed`.
+	fn title_changed(&mut self, title: String) -> bool {
+		let session = match &self.capture {
+			Some(session) => session,
+			None => return true,
+		};
+		let result = match crate::lua::detached_window_view(&self.lua, session.window.clone()) {
+			Ok(window) => call_hook(
+				&self.lua,
+				self.settings.time_limit(),
+				"on_title_changed",
+				(window, session.window.title.as_str(), title.as_str()),
+			)
+			.map(|value| matches!(value, Some(Value::Boolean(false)))),
+			Err(_) => Ok(false),
+		};
+		let veto = match result {
+			Ok(veto) => veto,
+			Err(err) => {
+				self.script_failed(err, "failed to call on_title_changed");
+				false
+			}
+		};
+		match &mut self.capture {
+			Some(session) if veto => session.window.title = title,
+			_ => {}
+		}
+		!veto
+	}
+
+	/// Creates a VM set up for this source.
+	fn new_lua(&self) -> Lua {
+		let lua = crate::lua::setup_luau_context();
+		crate::lua::set_source_name(&lua, &source_name(self.source));
+		crate::lua::set_memory_limit(&lua, self.settings.memory_limit_bytes());
+		crate::lua::set_module_roots(&lua, self.settings.module_roots());
+		lua
+	}
+
+	/// Replaces the VM with a fresh one, discarding the script's state, and
+	/// reloads the saved script into it.
+	fn recreate_lua(&mut self) {
+		self.loaded_script = self.script_hash();
+		self.load_saved_script(true);
+	}
+
+	/// Runs `load` against a fresh VM, so that globals, modules and anything
+	/// else an earlier version of the script left behind can't leak into the
+	/// new one. Only the JSON-safe contents of the script's `state` table are
+	/// carried over, unless `reload` discards them.
+	fn load_fresh<T>(&mut self, reload: Reload, load: impl FnOnce(&Lua) -> Result<T>) -> Result<T> {
+		if let Reload::Discard = reload {
+			self.lua = self.new_lua();
+			return load(&self.lua);
+		}
+		let lua = self.new_lua();
+		let state = match self.lua.named_registry_value::<Option<Table>>("state") {
+			Ok(Some(state)) => match state_to_json(&self.lua, &state) {
+				Ok(json) => Some(json),
+				Err(err) => {
+					warn!(
+						"failed to carry script state over to the new script: {:#}",
+						err
+					);
+					None
+				}
+			},
+			_ => None,
+		};
+		if let Some(json) = state {
+			if let Err(err) = script_state(&lua, || Some(ObsString::from(json))) {
+				warn!(
+					"failed to carry script state over to the new script: {}",
+					err
+				);
+			}
+		}
+		let result = load(&lua);
+		if result.is_ok() || !matches!(reload, Reload::KeepOnFailure) {
+			self.lua = lua;
+		}
+		result
+	}
+
+	/// Hashes everything that decides what the selector loads: the inline
+	/// script, or the script file's path and modification time, along with
+	/// the directories modules are loaded from. Returns `None` if the script
+	/// file can't be read.
+	fn script_hash(&self) -> Option<u64> {
+		let mut hasher = DefaultHasher::new();
+		self.settings.use_script_file.hash(&mut hasher);
+		if self.settings.use_script_file {
+			let path = Path::new(&self.settings.script_file);
+			path.hash(&mut hasher);
+			crate::util::script_modified(path).ok()?.hash(&mut hasher);
+		} else {
+			self.settings.script.hash(&mut hasher);
+		}
+		self.settings.module_roots().hash(&mut hasher);
+		Some(hasher.finish())
+	}
+
+	/// Loads the selector from either the script file or the inline script,
+	/// depending on the source's settings. The script is only evaluated again
+	/// if it, or a module it required, has changed since it was last loaded.
+	fn load_selector(&mut self) {
+		let hash = self.script_hash();
+		if hash.is_some() && hash == self.loaded_script && !crate::lua::modules_changed(&self.lua) {
+			self.refresh_script_values();
+			return;
+		}
+		self.loaded_script = hash;
+		self.load_saved_script(false);
+	}
+
+	/// Loads the saved script into a fresh VM, recording any error. The old
+	/// VM is replaced even if the script fails to load, and if `discard` is
+	/// set, the script's state isn't carried over either.
+	fn load_saved_script(&mut self, discard: bool) {
+		let budget = self.settings.time_limit();
+		let reload = if discard {
+			Reload::Discard
+		} else {
+			Reload::Replace
+		};
+		let result = if self.settings.use_script_file {
+			let path = PathBuf::from(&self.settings.script_file);
+			// The previous selector is only kept when a modified file fails
+			// to reload, in reload_modified_script. After switching to a file,
+			// or to a different one, a file that fails to load must not leave
+			// the old script selecting windows. Recording the modification
+			// time up front keeps a broken file from being reloaded again
+			// until it changes.
+			self.script_modified = crate::util::script_modified(&path).ok();
+			self.load_fresh(reload, |lua| {
+				crate::util::load_script_file(lua, &path, budget)
+			})
+			.map(|modified| self.script_modified = Some(modified))
+			.context("failed to load script file")
+		} else {
+			self.script_modified = None;
+			let script = self.settings.script.clone();
+			self.load_fresh(reload, |lua| crate::util::load_script(lua, &script, budget))
+				.context("failed to load script")
+		};
+		match result {
+			Ok(()) => self.status.lock().last_error = None,
+			Err(err) => self.record_error(err),
+		}
+		self.refresh_script_values();
+	}
+
+	/// Re-reads the values of the settings declared by the loaded script,
+	/// registering their defaults first. If the script declares different
+	/// settings than the last one, OBS is asked to rebuild the properties, so
+	/// an open properties dialog shows them.
+	fn refresh_script_values(&mut self) {
+		let schema = self
+			.lua
+			.app_data_ref::<SettingsSchema>()
+			.map(|schema| schema.clone())
+			.unwrap_or_default();
+		let mut settings = source_settings(self.source);
+		schema.set_defaults(&mut settings);
+		self.script_values = schema.values(&settings);
+		let changed = {
+			let mut status = self.status.lock();
+			let changed = status.schema != schema;
+			status.schema = schema;
+			changed
+		};
+		if changed {
+			unsafe { obs_source_update_properties(self.source) };
+		}
+	}
+
+	/// Reloads the script file if it has been modified since it was last
+	/// loaded, returning whether a new selector was loaded.
+	fn reload_modified_script(&mut self) -> bool {
+		let path = PathBuf::from(&self.settings.script_file);
+		let modified = match crate::util::script_modified(&path) {
+			Ok(modified) => modified,
+			Err(_) => return false,
+		};
+		if self.script_modified == Some(modified) {
+			return false;
+		}
+		// Record the new time even if loading fails, so a broken file isn't
+		// reloaded (and logged) over and over until it changes again.
+		self.script_modified = Some(modified);
+		self.loaded_script = self.script_hash();
+		let budget = self.settings.time_limit();
+		match self.load_fresh(Reload::KeepOnFailure, |lua| {
+			crate::util::load_script_file(lua, &path, budget)
+		}) {
+			Ok(_) => {
+				info!("reloaded script file {}", path.display());
+				self.status.lock().last_error = None;
+				self.refresh_script_values();
+				true
+			}
+			Err(err) => {
+				self.record_error(err.context("failed to reload script file"));
+				false
+			}
+		}
+	}
+
+	/// Runs the selector, backing off exponentially if it keeps hitting its
+	/// time or memory limit.
+	fn reselect(&mut self) {
+		match self.run_callbacks() {
+			Ok(()) => self.backoff = 0.0,
+			Err(err) => self.script_failed(err, "failed to run callbacks"),
+		}
+	}
+
+	/// Runs the selector, unless a hook just hit its time or memory limit, in
+	/// which case it's left for when the backoff ends.
+	fn reselect_unless_backed_off(&mut self) {
+		if self.cooldown <= 0.0 {
+			self.reselect();
+		}
+	}
+
+	/// Records an error from running the script. If the script hit its time
+	/// or memory limit, the selector is backed off, and if it ran out of
+	/// memory, the VM is recreated, since the script may not be able to
+	/// recover by itself.
+	fn script_failed(&mut self, err: anyhow::Error, context: &str) {
+		if !err.is::<ScriptTimeout>() && !err.is::<ScriptOutOfMemory>() {
+			self.record_error(err.context(context.to_owned()));
+			return;
+		}
+		if err.is::<ScriptOutOfMemory>() {
+			self.recreate_lua();
+		}
+		self.backoff = (self.backoff * 2.0).clamp(2.0, MAX_BACKOFF);
+		self.cooldown = self.backoff;
+		let backoff = self.backoff;
+		self.record_error(err.context(format!("{context}; retrying in {backoff}s")));
+	}
+
+	/// Logs a script error and keeps it to show in the source's properties.
+	fn record_error(&mut self, err: anyhow::Error) {
+		error!("{:#}", err);
+		self.status.lock().last_error = Some(ScriptFailure {
+			message: format!("{err:#}"),
+			at: SystemTime::now(),
+		});
+	}
+
+	/// Publishes the current capture to the status shown in the properties.
+	fn publish_capture_status(&self) {
+		let mut status = self.status.lock();
+		let shown = &mut status.capture;
+		match (&self.capture, shown) {
+			(Some(session), Some(shown))
+				if shown.hwnd == session.window.hwnd && shown.title == session.window.title =>
+			{
+				shown.width = session.capture.width();
+				shown.height = session.capture.height();
+				shown.active = session.capture.active();
+			}
+			(Some(session), shown) => {
+				*shown = Some(CaptureStatus {
+					title: session.window.title.clone(),
+					process: session.process.clone(),
+					hwnd: session.window.hwnd,
+					candidate: session.candidate,
+					width: session.capture.width(),
+					height: session.capture.height(),
+					active: session.capture.active(),
+				});
+			}
+			(None, shown) => *shown = None,
+		}
+	}
+
+	/// Adds read-only properties describing the script's last error and the
+	/// current capture, along with a button to refresh them.
+	fn add_status_properties(&self, props: &mut Properties) {
+		let status = self.status.lock();
+		let error = match &status.last_error {
+			Some(failure) => format!("{}\n{}", format_time(failure.at), failure.message),
+			None => "None".to_owned(),
+		};
+		add_info(
+			props,
+			"status_error",
+			&format!("Last Script Error: {error}"),
+		);
+		let selection = match &status.capture {
+			Some(capture) => format!(
+				"{:?} of {} (hwnd {:#x}, {}x{}, candidate {})",
+				capture.title,
+				capture.process,
+				capture.hwnd,
+				capture.width,
+				capture.height,
+				capture.candidate
+			),
+			None => "None".to_owned(),
+		};
+		add_info(
+			props,
+			"status_selection",
+			&format!("Selected Window: {selection}"),
+		);
+		let active = status
+			.capture
+			.as_ref()
+			.is_some_and(|capture| capture.active);
+		add_info(
+			props,
+			"status_capture",
+			&format!("Capture Active: {}", if active { "Yes" } else { "No" }),
+		);
+		let name = obs_string!("status_refresh");
+		let text = obs_string!("Refresh");
+		unsafe {
+			obs_properties_add_button(
+				props.as_ptr_mut(),
+				name.as_ptr(),
+				text.as_ptr(),
+				Some(refresh_properties),
+			);
+		}
+	}
+
+	/// Clears any timeout backoff and runs the selector immediately.
+	fn reselect_now(&mut self) {
+		self.backoff = 0.0;
+		self.cooldown = 0.0;
+		self.reselect();
+	}
+}
+
+/// Calls the script's `name` hook, if it defined one, returning its result.
+fn call_hook<'lua>(
+	lua: &'lua Lua,
+	budget: Duration,
+	name: &str,
+	args: impl IntoLuaMulti<'lua>,
+) -> Result<Option<Value<'lua>>> {
+	let hook = match lua.named_registry_value::<Option<Function>>(name) {
+		Ok(Some(hook)) => hook,
+		Ok(None) => return Ok(None),
+		Err(err) => return Err(crate::lua::script_error(lua, err)),
+	};
+	crate::lua::call_with_budget::<_, Value>(lua, &hook, args, budget).map(Some)
+}
+
+/// Gets the `state` table passed to the script, creating it (from the
+/// persisted copy returned by `persisted`, if any) if this VM doesn't have one
+/// yet.
+fn script_state(
+	lua: &Lua,
+	persisted: impl FnOnce() -> Option<ObsString>,
+) -> mlua::Result<Table<'_>> {
+	if let Some(state) = lua.named_registry_value::<Option<Table>>("state")? {
+		return Ok(state);
+	}
+	let persisted = persisted().and_then(|json| {
+		match serde_json::from_str::<serde_json::Value>(json.as_str()) {
+			Ok(value) => Some(value),
+			Err(err) => {
+				warn!("discarding unreadable persisted script state: {}", err);
+				None
+			}
+		}
+	});
+	let state = match persisted.map(|value| crate::lua::from_json(lua, &value)) {
+		Some(Ok(Value::Table(table))) => table,
+		Some(Err(err)) => return Err(err),
+		_ => lua.create_table()?,
+	};
+	lua.set_named_registry_value("state", &state)?;
+	Ok(state)
+}
+
+/// Converts the JSON-safe parts of the script's `state` table to JSON,
+/// skipping functions, threads and cycles.
+fn state_to_json(lua: &Lua, state: &Table) -> Result<String> {
+	let value = crate::lua::to_json(lua, Value::Table(state.clone()))
+		.context("state can't be converted to JSON")?;
+	Ok(serde_json::to_string(&value)?)
+}
+
+/// Gets the source's private settings, which OBS saves alongside the scene
+/// collection but doesn't show to the user.
+fn private_settings(source: *mut obs_source_t) -> DataObj<'static> {
+	unsafe { DataObj::from_raw(obs_source_get_private_settings(source)) }
+}
+
+/// `OBS_TEXT_INFO`, which obs-sys's bindings predate; it has been supported
+/// since OBS 26.1.
+const OBS_TEXT_INFO: obs_text_type = 3;
+
+/// Adds a read-only line of text to `props`.
+fn add_info(props: &mut Properties, name: &str, text: &str) {
+	let name = ObsString::from(name);
+	let text = ObsString::from(text);
+	unsafe {
+		obs_properties_add_text(
+			props.as_ptr_mut(),
+			name.as_ptr(),
+			text.as_ptr(),
+			OBS_TEXT_INFO,
+		);
+	}
+}
+
+/// Tells OBS to rebuild the properties, which re-reads the status.
+unsafe extern "C" fn refresh_properties(
+	_props: *mut obs_properties_t,
+	_property: *mut obs_property_t,
+	_data: *mut c_void,
+) -> bool {
+	true
+}
+
+/// Formats a point in time as a UTC time of day, along with how long ago it
+/// was.
+fn format_time(at: SystemTime) -> String {
+	let seconds = at
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |since| since.as_secs());
+	let ago = SystemTime::now()
+		.duration_since(at)
+		.map_or(0, |ago| ago.as_secs());
+	format!(
+		"{:02}:{:02}:{:02} UTC ({}s ago)",
+		seconds / 3600 % 24,
+		seconds / 60 % 60,
+		seconds % 60,
+		ago
+	)
+}
+
+/// Gets the source's settings, as shown in its properties.
+fn source_settings(source: *mut obs_source_t) -> DataObj<'static> {
+	unsafe { DataObj::from_raw(obs_source_get_settings(source)) }
+}
+
+/// Gets the source's current name, which the user can change at any time.
+fn source_name(source: *mut obs_source_t) -> String {
+	let name = unsafe { obs_source_get_name(source) };
+	if name.is_null() {
+		return String::new();
+	}
+	unsafe { CStr::from_ptr(name) }
+		.to_string_lossy()
+		.into_owned()
+}
+
+fn set_private_string(source: *mut obs_source_t, key: &str, value: &str) {
+	let mut settings = private_settings(source);
+	let key = ObsString::from(key);
+	let value = ObsString::from(value);
+	unsafe {
+		obs_data_set_string(settings.as_ptr_mut(), key.as_ptr(), value.as_ptr());
+	}
 }
 
 impl Sourceable for WincatSource {
@@ -86,13 +775,28 @@ fn get_type() -> SourceType {
 		SourceType::INPUT
 	}
 
-	fn create(create: &mut CreatableSourceContext<Self>, _source: SourceContext) -> Self {
+	fn create(create: &mut CreatableSourceContext<Self>, source: SourceContext) -> Self {
+		// SourceContext::id() is the raw obs_source_t pointer. Dropping the
+		// context at the end of this function releases its reference.
+		let source = source.id() as *mut obs_source_t;
 		let lua = crate::lua::setup_luau_context();
+		crate::lua::set_source_name(&lua, &source_name(source));
 		let mut this = Self {
+			source,
 			lua,
 			settings: Settings::default(),
 			ticks: 0.0,
+			next_check: None,
+			backoff: 0.0,
+			cooldown: 0.0,
+			script_poll_ticks: 0.0,
+			script_modified: None,
+			loaded_script: None,
+			persisted_state: None,
+			persist_failed: false,
+			script_values: Vec::new(),
 			capture: None,
+			status: Arc::default(),
 		};
 		this.update(&mut create.settings, create.global);
 		this
@@ -113,6 +817,28 @@ fn get_properties(&mut self) -> Properties {
 			obs_string!("Selector Script (Luau)"),
 			TextProp::new(TextType::Multiline),
 		);
+		props.add(
+			obs_string!("use_script_file"),
+			obs_string!("Load Selector Script From File"),
+			BoolProp,
+		);
+		props.add(
+			obs_string!("script_file"),
+			obs_string!("Selector Script File"),
+			PathProp::new(PathType::File)
+				.with_filter(obs_string!("Luau scripts (*.luau *.lua);;All files (*.*)")),
+		);
+		self.status.lock().schema.add_properties(&mut props);
+		props.add(
+			obs_string!("modules_dir"),
+			obs_string!("Additional Module Directory"),
+			PathProp::new(PathType::Directory),
+		);
+		props.add(
+			obs_string!("persist_state"),
+			obs_string!("Persist Script State Across Restarts"),
+			BoolProp,
+		);
 		props.add(
 			obs_string!("cursor"),
 			obs_string!("Capture Cursor"),
@@ -124,6 +850,27 @@ fn get_properties(&mut self) -> Properties {
 			BoolProp,
 		);
 		props.add(obs_string!("force_sdr"), obs_string!("Force SDR"), BoolProp);
+		props.add(
+			obs_string!("retry_interval"),
+			obs_string!("Retry Interval (s)"),
+			NumberProp::new_float(0.5).with_range(0.5..=600.0),
+		);
+		props.add(
+			obs_string!("title_check_interval"),
+			obs_string!("Title Check Interval (s)"),
+			NumberProp::new_float(0.5).with_range(0.5..=600.0),
+		);
+		props.add(
+			obs_string!("time_limit_ms"),
+			obs_string!("Script Time Limit (ms)"),
+			NumberProp::<u32>::new_int().with_range(1..=10_000),
+		);
+		props.add(
+			obs_string!("memory_limit_mb"),
+			obs_string!("Script Memory Limit (MiB)"),
+			NumberProp::<u32>::new_int().with_range(1..=1024),
+		);
+		self.add_status_properties(&mut props);
 		props
 	}
 }
@@ -134,6 +881,23 @@ fn update(&mut self, settings: &mut DataObj, _context: &mut GlobalContext) {
 			.get::<ObsString>(obs_string!("script"))
 			.map(|os| os.as_str().to_string())
 			.unwrap_or_default();
+		self.settings.use_script_file = settings
+			.get::<bool>(obs_string!("use_script_file"))
+			.unwrap_or(false);
+		self.settings.script_file = settings
+			.get::<ObsString>(obs_string!("script_file"))
+			.map(|os| os.as_str().to_string())
+			.unwrap_or_default();
+		self.settings.modules_dir = settings
+			.get::<ObsString>(obs_string!("modules_dir"))
+			.map(|os| os.as_str().to_string())
+			.unwrap_or_default();
+		self.settings.persist_state = settings
+			.get::<bool>(obs_string!("persist_state"))
+			.unwrap_or(false);
+		if !self.settings.persist_state && self.persisted_state.take().is_some() {
+			private_settings(self.source).remove(STATE_KEY);
+		}
 		self.settings.cursor = settings.get::<bool>(obs_string!("cursor")).unwrap_or(true);
 		self.settings.client_area = settings
 			.get::<bool>(obs_string!("client_area"))
@@ -141,28 +905,49 @@ fn update(&mut self, settings: &mut DataObj, _context: &mut GlobalContext) {
 		self.settings.force_sdr = settings
 			.get::<bool>(obs_string!("force_sdr"))
 			.unwrap_or(false);
-		crate::util::load_script(&self.lua, &self.settings.script);
-		if let Err(err) = self.run_callbacks() {
-			error!("failed to run callbacks: {}", err);
-		}
+		self.settings.retry_interval = settings
+			.get::<f64>(obs_string!("retry_interval"))
+			.unwrap_or(DEFAULT_RETRY_INTERVAL) as f32;
+		self.settings.title_check_interval = settings
+			.get::<f64>(obs_string!("title_check_interval"))
+			.unwrap_or(DEFAULT_TITLE_CHECK_INTERVAL) as f32;
+		self.settings.time_limit_ms = settings
+			.get::<u32>(obs_string!("time_limit_ms"))
+			.unwrap_or(DEFAULT_TIME_LIMIT_MS);
+		self.settings.memory_limit_mb = settings
+			.get::<u32>(obs_string!("memory_limit_mb"))
+			.unwrap_or(DEFAULT_MEMORY_LIMIT_MB);
+		crate::lua::set_memory_limit(&self.lua, self.settings.memory_limit_bytes());
+		crate::lua::set_module_roots(&self.lua, self.settings.module_roots());
+		self.load_selector();
+		self.reselect_now();
 	}
 }
 
 impl GetDefaultsSource for WincatSource {
 	fn get_defaults(settings: &mut DataObj) {
 		settings.set_default::<ObsString>(obs_string!("script"), obs_string!(""));
+		settings.set_default::<bool>(obs_string!("use_script_file"), false);
+		settings.set_default::<ObsString>(obs_string!("script_file"), obs_string!(""));
+		settings.set_default::<ObsString>(obs_string!("modules_dir"), obs_string!(""));
+		settings.set_default::<bool>(obs_string!("persist_state"), false);
 		settings.set_default::<bool>(obs_string!("cursor"), true);
 		settings.set_default::<bool>(obs_string!("client_area"), false);
 		settings.set_default::<bool>(obs_string!("force_sdr"), false);
+		settings.set_default::<f64>(obs_string!("retry_interval"), DEFAULT_RETRY_INTERVAL);
+		settings.set_default::<f64>(
+			obs_string!("title_check_interval"),
+			DEFAULT_TITLE_CHECK_INTERVAL,
+		);
+		settings.set_default::<u32>(obs_string!("time_limit_ms"), DEFAULT_TIME_LIMIT_MS);
+		settings.set_default::<u32>(obs_string!("memory_limit_mb"), DEFAULT_MEMORY_LIMIT_MB);
 	}
 }
 
 impl ActivateSource for WincatSource {
 	fn activate(&mut self) {
-		crate::util::load_script(&self.lua, &self.settings.script);
-		if let Err(err) = self.run_callbacks() {
-			error!("failed to run callbacks: {}", err);
-		}
+		self.load_selector();
+		self.reselect_now();
 	}
 }
 
@@ -172,38 +957,67 @@ fn deactivate(&mut self) {}
 
 impl VideoTickSource for WincatSource {
 	fn video_tick(&mut self, seconds: f32) {
+		self.tick(seconds);
+		self.publish_capture_status();
+	}
+}
+
+impl WincatSource {
+	/// Reloads the script if it changed, and reselects the window when it's
+	/// time to.
+	fn tick(&mut self, seconds: f32) {
+		self.script_poll_ticks += seconds;
+		if self.script_poll_ticks >= SCRIPT_FILE_POLL_INTERVAL {
+			self.script_poll_ticks = 0.0;
+			// Sources can be renamed at any time, without an update.
+			crate::lua::set_source_name(&self.lua, &source_name(self.source));
+			let reloaded = if crate::lua::modules_changed(&self.lua) {
+				info!("required module changed; reloading script");
+				self.load_selector();
+				true
+			} else {
+				self.settings.use_script_file && self.reload_modified_script()
+			};
+			if reloaded {
+				self.reselect_now();
+				return;
+			}
+		}
+		if self.cooldown > 0.0 {
+			self.cooldown -= seconds;
+			return;
+		}
 		self.ticks += seconds;
 		let capture = match self.capture.as_ref() {
 			Some(capture) => capture,
 			None => {
-				if self.ticks >= 2.0 {
+				if self.ticks >= self.next_check.unwrap_or(self.settings.retry_interval) {
 					self.ticks = 0.0;
-					if let Err(err) = self.run_callbacks() {
-						error!("failed to run callbacks: {}", err);
-					}
+					self.reselect();
 				}
 				return;
 			}
 		};
 
 		if !capture.capture.active() {
-			std::mem::drop(self.capture.take());
+			self.drop_capture("inactive");
 			warn!("capture inactive; running callbacks");
-			if let Err(err) = self.run_callbacks() {
-				error!("failed to run callbacks: {}", err);
-			}
+			self.reselect_unless_backed_off();
 			return;
 		}
 
-		if self.ticks >= 5.0 {
+		if self.ticks
+			>= self
+				.next_check
+				.unwrap_or(self.settings.title_check_interval)
+		{
 			self.ticks = 0.0;
-			let title = crate::window::get_window_title(capture.hwnd);
-			if !title.is_empty() && title != capture.title {
-				std::mem::drop(self.capture.take());
+			let title =
+				selection::title_change(&capture.window, &*crate::module::window::provider());
+			if title.is_some_and(|title| self.title_changed(title)) {
+				self.drop_capture("title_changed");
 				warn!("window title changed; running callbacks");
-				if let Err(err) = self.run_callbacks() {
-					error!("failed to run callbacks: {}", err);
-				}
+				self.reselect_unless_backed_off();
 			}
 		}
 	}
@@ -237,18 +1051,55 @@ fn get_height(&mut self) -> u32 {
 
 struct Settings {
 	script: String,
+	use_script_file: bool,
+	script_file: String,
+	modules_dir: String,
+	persist_state: bool,
 	cursor: bool,
 	client_area: bool,
 	force_sdr: bool,
+	retry_interval: f32,
+	title_check_interval: f32,
+	time_limit_ms: u32,
+	memory_limit_mb: u32,
+}
+
+impl Settings {
+	fn time_limit(&self) -> Duration {
+		Duration::from_millis(self.time_limit_ms as u64)
+	}
+
+	fn memory_limit_bytes(&self) -> usize {
+		self.memory_limit_mb as usize * 1024 * 1024
+	}
+
+	/// The directories `require` resolves modules from: the shared scripts
+	/// directory, followed by the user's own module directory, if set.
+	fn module_roots(&self) -> Vec<PathBuf> {
+		let mut roots = Vec::with_capacity(2);
+		roots.extend(crate::module::SCRIPTS_DIR.get().cloned());
+		if !self.modules_dir.trim().is_empty() {
+			roots.push(PathBuf::from(&self.modules_dir));
+		}
+		roots
+	}
 }
 
 impl Default for Settings {
 	fn default() -> Self {
 		Self {
 			script: String::new(),
+			use_script_file: false,
+			script_file: String::new(),
+			modules_dir: String::new(),
+			persist_state: false,
 			cursor: true,
 			client_area: false,
 			force_sdr: false,
+			retry_interval: DEFAULT_RETRY_INTERVAL as f32,
+			title_check_interval: DEFAULT_TITLE_CHECK_INTERVAL as f32,
+			time_limit_ms: DEFAULT_TIME_LIMIT_MS,
+			memory_limit_mb: DEFAULT_MEMORY_LIMIT_MB,
 		}
 	}
 }
@@ -1,5 +1,12 @@
 // SPDX-License-Identifier: MPL-2.0
-use mlua::{Function, Lua};
+use crate::script_settings::SettingsSchema;
+use anyhow::{bail, Context, Result};
+use mlua::{Function, Lua, Table, Value};
+use std::{
+	path::Path,
+	time::{Duration, SystemTime},
+};
+#[cfg(windows)]
 use windows::Win32::{Foundation::HWND, UI::WindowsAndMessaging::GetWindowThreadProcessId};
 
 pub(crate) trait TrimInPlace: Sized {
@@ -34,28 +41,139 @@ fn trim_in_place(self) -> Self {
 	}
 }
 
-pub(crate) fn load_script(lua: &Lua, script: &str) {
+/// The optional lifecycle hooks a script may return alongside
+/// `select_window`.
+pub(crate) const HOOKS: &[&str] = &["on_capture_started", "on_capture_lost", "on_title_changed"];
+
+/// Loads the selector from inline script text, aborting its top-level code
+/// if it runs longer than `budget`. On failure, the previous selector is
+/// unloaded, so a broken script never keeps running stale code.
+pub(crate) fn load_script(lua: &Lua, script: &str, budget: Duration) -> Result<()> {
 	if script.trim().is_empty() {
-		if let Err(err) = lua.unset_named_registry_value("select_window") {
-			error!("Failed to unset select_window: {:?}", err);
-		}
-		return;
+		clear_handlers(lua);
+		return Ok(());
+	}
+	let result = crate::lua::with_budget(lua, budget, || {
+		lua.load(script).set_name("=script").eval::<Value>()
+	})
+	.context("failed to evaluate script")
+	.and_then(|value| set_handlers(lua, value));
+	if result.is_err() {
+		clear_handlers(lua);
 	}
-	match lua.load(script).eval::<Function>() {
-		Ok(func) => {
-			if let Err(err) = lua.set_named_registry_value("select_window", func) {
-				error!("Failed to set select_window: {:?}", err);
-			}
+	result
+}
+
+/// Loads the selector from the file at `path`, aborting its top-level code
+/// if it runs longer than `budget`, and returns the file's modification time.
+/// On failure, any selector already loaded into `lua` is left in place.
+pub(crate) fn load_script_file(lua: &Lua, path: &Path, budget: Duration) -> Result<SystemTime> {
+	let modified = script_modified(path)?;
+	let script = std::fs::read_to_string(path)
+		.with_context(|| format!("failed to read {}", path.display()))?;
+	let value = crate::lua::with_budget(lua, budget, || {
+		lua.load(&script)
+			.set_name(format!("@{}", path.display()))
+			.eval::<Value>()
+	})
+	.with_context(|| format!("failed to load {}", path.display()))?;
+	set_handlers(lua, value)?;
+	Ok(modified)
+}
+
+/// Stores the handlers returned by a script: either a single function,
+/// which becomes `select_window`, or a table with a `select_window` function,
+/// any of the [`HOOKS`] and an optional `settings` schema.
+///
+/// mlua doesn't expose Luau's type checker, so scripts aren't checked against
+/// `wincat.d.luau`; only the shape of the returned value is checked here, and
+/// what `select_window` returns is checked each time it's called.
+fn set_handlers(lua: &Lua, value: Value) -> Result<()> {
+	let (select_window, handlers) = match value {
+		Value::Function(func) => (func, None),
+		Value::Table(table) => (
+			table
+				.get::<_, Function>("select_window")
+				.context("script table must have a select_window function")?,
+			Some(table),
+		),
+		other => bail!(
+			"script must return a function or a table of handlers, not {}",
+			other.type_name()
+		),
+	};
+	if let Some(table) = &handlers {
+		warn_unknown_hooks(table);
+	}
+	let mut hooks = Vec::with_capacity(HOOKS.len());
+	for hook in HOOKS {
+		let func = match &handlers {
+			Some(table) => table
+				.get::<_, Option<Function>>(*hook)
+				.with_context(|| format!("{hook} must be a function"))?,
+			None => None,
+		};
+		hooks.push((*hook, func));
+	}
+	let schema = match &handlers {
+		Some(table) => match table
+			.get::<_, Option<Table>>("settings")
+			.context("settings must be an array of tables")?
+		{
+			Some(settings) => SettingsSchema::parse(settings).context("invalid settings")?,
+			None => SettingsSchema::default(),
+		},
+		None => SettingsSchema::default(),
+	};
+	lua.set_named_registry_value("select_window", select_window)
+		.context("failed to set select_window")?;
+	for (hook, func) in hooks {
+		lua.set_named_registry_value(hook, func)
+			.with_context(|| format!("failed to set {hook}"))?;
+	}
+	lua.set_app_data(schema);
+	Ok(())
+}
+
+/// Warns about `on_*` functions in a handler table that don't name a known
+/// hook, since a misspelled hook would otherwise never be called.
+fn warn_unknown_hooks(table: &Table) {
+	for (key, value) in table.clone().pairs::<Value, Value>().flatten() {
+		let key = match &key {
+			Value::String(key) => key.to_string_lossy(),
+			_ => continue,
+		};
+		if key.starts_with("on_")
+			&& matches!(value, Value::Function(_))
+			&& !HOOKS.contains(&key.as_ref())
+		{
+			warn!(
+				"script returned an unknown hook '{}'; it will never be called (expected one of \
+				 {})",
+				key,
+				HOOKS.join(", ")
+			);
 		}
-		Err(err) => {
-			error!("Failed to load script: {:?}", err);
-			if let Err(err) = lua.unset_named_registry_value("select_window") {
-				error!("Failed to unset select_window: {:?}", err);
-			}
+	}
+}
+
+fn clear_handlers(lua: &Lua) {
+	lua.remove_app_data::<SettingsSchema>();
+	for name in std::iter::once(&"select_window").chain(HOOKS) {
+		if let Err(err) = lua.unset_named_registry_value(name) {
+			error!("Failed to unset {}: {:?}", name, err);
 		}
-	};
+	}
+}
+
+/// Gets the last modification time of the script file at `path`.
+pub(crate) fn script_modified(path: &Path) -> Result<SystemTime> {
+	std::fs::metadata(path)
+		.and_then(|metadata| metadata.modified())
+		.with_context(|| format!("failed to stat {}", path.display()))
 }
 
+#[cfg(windows)]
 pub(crate) fn hwnd_to_pid(hwnd: isize) -> Option<u32> {
 	let mut process_id = 0;
 	match unsafe { GetWindowThreadProcessId(HWND(hwnd), Some(&mut process_id)) } {
@@ -0,0 +1,198 @@
+// SPDX-License-Identifier: MPL-2.0
+use ahash::AHashMap;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use windows::{
+	core::{PCWSTR, PWSTR},
+	Wdk::System::Threading::{NtQueryInformationProcess, ProcessCommandLineInformation},
+	Win32::{
+		Foundation::{CloseHandle, FILETIME, HANDLE, UNICODE_STRING},
+		Security::{
+			GetTokenInformation, LookupAccountSidW, TokenUser, SID_NAME_USE, TOKEN_QUERY,
+			TOKEN_USER,
+		},
+		System::{
+			RemoteDesktop::ProcessIdToSessionId,
+			Threading::{
+				GetProcessTimes, OpenProcess, OpenProcessToken, QueryFullProcessImageNameW,
+				PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+			},
+		},
+	},
+};
+use wtf8::Wtf8Buf;
+
+/// The seconds between 1601-01-01, the epoch of a `FILETIME`, and the Unix
+/// epoch.
+const FILETIME_UNIX_OFFSET: f64 = 11_644_473_600.0;
+
+/// Details of a process that can only be read through a handle to it.
+/// Protected processes, and elevated ones when OBS isn't, refuse to be
+/// opened, so every field is optional.
+#[derive(Debug, Default, Clone)]
+pub(super) struct ProcessInfo {
+	pub exe_path: Option<String>,
+	pub command_line: Option<String>,
+	pub start_time: Option<f64>,
+	pub session_id: Option<u32>,
+	pub user: Option<String>,
+}
+
+/// Details already read, by pid, so each process is only queried in full
+/// once. Entries are checked against the process's start time, in case its
+/// pid has been reused.
+static CACHE: Lazy<Mutex<AHashMap<u32, ProcessInfo>>> = Lazy::new(Default::default);
+
+/// Reads the details of the process with the given pid.
+pub(super) fn query(pid: u32) -> ProcessInfo {
+	let mut session_id = 0;
+	let session_id = unsafe { ProcessIdToSessionId(pid, &mut session_id) }
+		.ok()
+		.map(|_| session_id);
+	let handle = match unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) } {
+		Ok(handle) => handle,
+		Err(_) => {
+			return ProcessInfo {
+				session_id,
+				..ProcessInfo::default()
+			}
+		}
+	};
+	scopeguard::defer! {
+		let _ = unsafe { CloseHandle(handle) };
+	};
+	let start_time = start_time(handle);
+	let mut cache = CACHE.lock();
+	if let Some(info) = cache.get(&pid) {
+		if start_time.is_some() && info.start_time == start_time {
+			return info.clone();
+		}
+	}
+	let info = ProcessInfo {
+		exe_path: exe_path(handle),
+		command_line: command_line(handle),
+		start_time,
+		session_id,
+		user: user(handle),
+	};
+	cache.insert(pid, info.clone());
+	info
+}
+
+/// Forgets the details of processes that are no longer running.
+pub(super) fn retain(mut is_running: impl FnMut(u32) -> bool) {
+	CACHE.lock().retain(|pid, _| is_running(*pid));
+}
+
+fn start_time(handle: HANDLE) -> Option<f64> {
+	let mut creation = FILETIME::default();
+	let mut exit = FILETIME::default();
+	let mut kernel = FILETIME::default();
+	let mut user = FILETIME::default();
+	unsafe { GetProcessTimes(handle, &mut creation, &mut exit, &mut kernel, &mut user) }.ok()?;
+	// FILETIMEs count 100ns intervals.
+	let ticks = (creation.dwHighDateTime as u64) << 32 | creation.dwLowDateTime as u64;
+	Some(ticks as f64 / 10_000_000.0 - FILETIME_UNIX_OFFSET)
+}
+
+fn exe_path(handle: HANDLE) -> Option<String> {
+	let mut buffer = [0; 1024];
+	let mut len = buffer.len() as u32;
+	unsafe {
+		QueryFullProcessImageNameW(
+			handle,
+			PROCESS_NAME_WIN32,
+			PWSTR(buffer.as_mut_ptr()),
+			&mut len,
+		)
+	}
+	.ok()?;
+	Some(Wtf8Buf::from_ill_formed_utf16(&buffer[..len as usize]).into_string_lossy())
+}
+
+fn command_line(handle: HANDLE) -> Option<String> {
+	// The first call fails, but says how big the buffer needs to be: a
+	// UNICODE_STRING followed by the text it points to.
+	let mut len = 0;
+	let _ = unsafe {
+		NtQueryInformationProcess(
+			handle,
+			ProcessCommandLineInformation,
+			std::ptr::null_mut(),
+			0,
+			&mut len,
+		)
+	};
+	if len == 0 {
+		return None;
+	}
+	let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+	let status = unsafe {
+		NtQueryInformationProcess(
+			handle,
+			ProcessCommandLineInformation,
+			buffer.as_mut_ptr().cast(),
+			len,
+			&mut len,
+		)
+	};
+	if status.is_err() {
+		return None;
+	}
+	let string = unsafe { &*buffer.as_ptr().cast::<UNICODE_STRING>() };
+	if string.Buffer.is_null() || string.Length == 0 {
+		return None;
+	}
+	let text = unsafe { std::slice::from_raw_parts(string.Buffer.0, string.Length as usize / 2) };
+	Some(Wtf8Buf::from_ill_formed_utf16(text).into_string_lossy())
+}
+
+/// Gets the `DOMAIN\user` account the process runs as.
+fn user(handle: HANDLE) -> Option<String> {
+	let mut token = HANDLE::default();
+	unsafe { OpenProcessToken(handle, TOKEN_QUERY, &mut token) }.ok()?;
+	scopeguard::defer! {
+		let _ = unsafe { CloseHandle(token) };
+	};
+	let mut len = 0;
+	let _ = unsafe { GetTokenInformation(token, TokenUser, None, 0, &mut len) };
+	if len == 0 {
+		return None;
+	}
+	let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+	unsafe {
+		GetTokenInformation(
+			token,
+			TokenUser,
+			Some(buffer.as_mut_ptr().cast()),
+			len,
+			&mut len,
+		)
+	}
+	.ok()?;
+	let token_user = unsafe { &*buffer.as_ptr().cast::<TOKEN_USER>() };
+	let mut name = [0; 256];
+	let mut name_len = name.len() as u32;
+	let mut domain = [0; 256];
+	let mut domain_len = domain.len() as u32;
+	let mut sid_use = SID_NAME_USE::default();
+	unsafe {
+		LookupAccountSidW(
+			PCWSTR::null(),
+			token_user.User.Sid,
+			PWSTR(name.as_mut_ptr()),
+			&mut name_len,
+			PWSTR(domain.as_mut_ptr()),
+			&mut domain_len,
+			&mut sid_use,
+		)
+	}
+	.ok()?;
+	let name = Wtf8Buf::from_ill_formed_utf16(&name[..name_len as usize]).into_string_lossy();
+	let domain = Wtf8Buf::from_ill_formed_utf16(&domain[..domain_len as usize]).into_string_lossy();
+	Some(if domain.is_empty() {
+		name
+	} else {
+		format!("{domain}\\{name}")
+	})
+}
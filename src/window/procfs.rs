@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: MPL-2.0
+use super::{Process, WindowProvider};
+use crate::capture::{Capture, CaptureConfig};
+use ahash::AHashMap;
+use parking_lot::Mutex;
+use std::{fs, path::Path, sync::Arc, time::SystemTime};
+
+/// The unit of the start times in `/proc/<pid>/stat`. The kernel always
+/// reports them in `USER_HZ`, which is 100 on every architecture Linux
+/// supports today, whatever `CONFIG_HZ` is.
+const USER_HZ: f64 = 100.0;
+
+/// Reads processes from Linux's `/proc`, so selector scripts can be run
+/// against real process data off Windows. It knows nothing about windows, so
+/// every process has none.
+#[derive(Debug, Clone)]
+pub struct ProcfsProvider {
+	/// When the system booted, in seconds since the Unix epoch.
+	boot_time: Option<f64>,
+	users: Arc<Mutex<Users>>,
+}
+
+/// User names by uid, as last read from `/etc/passwd`.
+#[derive(Debug, Default)]
+struct Users {
+	/// When `/etc/passwd` was modified as of the last read.
+	modified: Option<SystemTime>,
+	names: AHashMap<u32, String>,
+}
+
+impl ProcfsProvider {
+	pub fn new() -> Self {
+		Self {
+			boot_time: read_boot_time(),
+			users: Arc::default(),
+		}
+	}
+}
+
+impl Users {
+	/// Re-reads `/etc/passwd` if it has changed since it was last read.
+	/// Users from other sources, such as LDAP, aren't found.
+	fn refresh(&mut self) {
+		let modified = fs::metadata("/etc/passwd")
+			.and_then(|metadata| metadata.modified())
+			.ok();
+		if modified.is_some() && modified == self.modified {
+			return;
+		}
+		self.modified = modified;
+		self.names = fs::read_to_string("/etc/passwd")
+			.map(|passwd| {
+				passwd
+					.lines()
+					.filter_map(|line| {
+						let mut fields = line.split(':');
+						let name = fields.next()?;
+						let uid = fields.nth(1)?.parse().ok()?;
+						Some((uid, name.to_owned()))
+					})
+					.collect()
+			})
+			.unwrap_or_default();
+	}
+
+	/// Gets the name of the user with the given uid, or the uid itself if
+	/// it has no name.
+	fn name(&self, uid: u32) -> String {
+		self.names
+			.get(&uid)
+			.cloned()
+			.unwrap_or_else(|| uid.to_string())
+	}
+}
+
+impl Default for ProcfsProvider {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl WindowProvider for ProcfsProvider {
+	fn processes(&self, processes: &mut AHashMap<u32, Process>) {
+		let entries = match fs::read_dir("/proc") {
+			Ok(entries) => entries,
+			Err(err) => {
+				error!("failed to read /proc: {}", err);
+				return;
+			}
+		};
+		let mut users = self.users.lock();
+		users.refresh();
+		for entry in entries.flatten() {
+			let pid = match entry
+				.file_name()
+				.to_str()
+				.and_then(|name| name.parse().ok())
+			{
+				Some(pid) => pid,
+				None => continue,
+			};
+			// The process may exit while it's being read; skip it if so.
+			if let Some(process) = read_process(&entry.path(), pid, self.boot_time, &users) {
+				processes.insert(pid, process);
+			}
+		}
+	}
+
+	fn window_title(&self, _hwnd: isize) -> String {
+		String::new()
+	}
+
+	fn window_pid(&self, _hwnd: isize) -> Option<u32> {
+		None
+	}
+
+	fn is_window(&self, _hwnd: isize) -> bool {
+		false
+	}
+
+	/// Capture is only supported on Windows.
+	fn capture(&self, _hwnd: isize, _config: CaptureConfig) -> Option<Box<dyn Capture>> {
+		None
+	}
+}
+
+fn read_boot_time() -> Option<f64> {
+	let stat = fs::read_to_string("/proc/stat").ok()?;
+	stat.lines()
+		.find_map(|line| line.strip_prefix("btime "))
+		.and_then(|btime| btime.trim().parse::<u64>().ok())
+		.map(|btime| btime as f64)
+}
+
+fn read_process(dir: &Path, pid: u32, boot_time: Option<f64>, users: &Users) -> Option<Process> {
+	let stat = fs::read_to_string(dir.join("stat")).ok()?;
+	// The command name is in parentheses and may itself contain spaces and
+	// parentheses, so split on the last closing one.
+	let (comm, fields) = stat.split_once(" (")?.1.rsplit_once(") ")?;
+	let fields = fields.split_whitespace().collect::<Vec<_>>();
+	// Fields are counted from `state`, the third field of the file.
+	let parent_pid = fields.get(1).and_then(|ppid| ppid.parse().ok());
+	let session_id = fields.get(3).and_then(|session| session.parse().ok());
+	let start_time = fields
+		.get(19)
+		.and_then(|ticks| ticks.parse::<u64>().ok())
+		.zip(boot_time)
+		.map(|(ticks, boot_time)| boot_time + ticks as f64 / USER_HZ);
+	// The executable's path isn't truncated like `comm` is, but can only be
+	// read for our own processes.
+	let exe_path = fs::read_link(dir.join("exe")).ok().map(|exe| {
+		let exe = exe.to_string_lossy();
+		exe.strip_suffix(" (deleted)").unwrap_or(&exe).to_owned()
+	});
+	let name = exe_path
+		.as_deref()
+		.and_then(|exe| Path::new(exe).file_name())
+		.map(|name| name.to_string_lossy().into_owned())
+		.unwrap_or_else(|| comm.to_owned());
+	let command_line = fs::read(dir.join("cmdline"))
+		.ok()
+		.filter(|cmdline| !cmdline.is_empty())
+		.map(|cmdline| join_args(&cmdline));
+	let user = read_uid(dir).map(|uid| users.name(uid));
+	Some(Process {
+		name,
+		pid,
+		exe_path,
+		command_line,
+		parent_pid,
+		start_time,
+		session_id,
+		user,
+		..Process::default()
+	})
+}
+
+/// Reads the real uid of a process from `/proc/<pid>/status`.
+fn read_uid(dir: &Path) -> Option<u32> {
+	let status = fs::read_to_string(dir.join("status")).ok()?;
+	status
+		.lines()
+		.find_map(|line| line.strip_prefix("Uid:"))?
+		.split_whitespace()
+		.next()?
+		.parse()
+		.ok()
+}
+
+/// Joins the NUL-separated arguments in `/proc/<pid>/cmdline` into a single
+/// command line, quoting arguments that contain whitespace.
+fn join_args(cmdline: &[u8]) -> String {
+	cmdline
+		.strip_suffix(b"\0")
+		.unwrap_or(cmdline)
+		.split(|&byte| byte == 0)
+		.map(|arg| {
+			let arg = String::from_utf8_lossy(arg);
+			if arg.is_empty() || arg.contains(char::is_whitespace) {
+				format!("\"{}\"", arg.replace('"', "\\\""))
+			} else {
+				arg.into_owned()
+			}
+		})
+		.collect::<Vec<_>>()
+		.join(" ")
+}
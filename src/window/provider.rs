@@ -0,0 +1,157 @@
+// SPDX-License-Identifier: MPL-2.0
+use super::{Process, Window};
+use crate::capture::{Capture, CaptureConfig};
+use ahash::AHashMap;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Where process and window snapshots come from.
+pub trait WindowProvider: Send + Sync {
+	/// Fills `processes` with every running process, keyed by pid, along with
+	/// their windows.
+	fn processes(&self, processes: &mut AHashMap<u32, Process>);
+
+	/// Gets the current title of a window, or an empty string if it has none.
+	fn window_title(&self, hwnd: isize) -> String;
+
+	/// Gets the pid of the process that owns a window, if it still exists.
+	fn window_pid(&self, hwnd: isize) -> Option<u32>;
+
+	/// Checks whether a window still exists.
+	fn is_window(&self, hwnd: isize) -> bool;
+
+	/// Starts capturing a window, or returns `None` if it can't be captured.
+	fn capture(&self, hwnd: isize, config: CaptureConfig) -> Option<Box<dyn Capture>>;
+}
+
+/// Serves a process list held in memory, so selection logic can be driven
+/// deterministically without touching the real desktop. Clones share the
+/// same list, so a test can keep one to change what the others see.
+#[derive(Clone, Default)]
+pub struct FakeProvider {
+	processes: Arc<Mutex<Vec<Process>>>,
+}
+
+impl FakeProvider {
+	pub fn new(processes: Vec<Process>) -> Self {
+		Self {
+			processes: Arc::new(Mutex::new(processes)),
+		}
+	}
+
+	/// Replaces the whole process list.
+	pub fn set_processes(&self, processes: Vec<Process>) {
+		*self.processes.lock() = processes;
+	}
+
+	/// Changes the title of a window, returning whether it was found.
+	pub fn set_title(&self, hwnd: isize, title: &str) -> bool {
+		let mut processes = self.processes.lock();
+		let mut found = false;
+		for window in processes
+			.iter_mut()
+			.flat_map(|process| process.main.iter_mut().chain(process.windows.iter_mut()))
+		{
+			if window.hwnd == hwnd {
+				window.title = title.to_owned();
+				found = true;
+			}
+		}
+		found
+	}
+
+	/// Closes a window, returning whether it was found.
+	pub fn close_window(&self, hwnd: isize) -> bool {
+		let mut processes = self.processes.lock();
+		let mut found = false;
+		for process in processes.iter_mut() {
+			let count = process.windows.len();
+			process.windows.retain(|window| window.hwnd != hwnd);
+			found |= process.windows.len() != count;
+			if process.main.as_ref().is_some_and(|main| main.hwnd == hwnd) {
+				process.main = process
+					.windows
+					.iter()
+					.find(|window| window.visible)
+					.cloned();
+			}
+		}
+		found
+	}
+
+	fn find_window<R>(&self, hwnd: isize, f: impl FnOnce(&Process, &Window) -> R) -> Option<R> {
+		let processes = self.processes.lock();
+		processes
+			.iter()
+			.find_map(|process| {
+				process
+					.windows
+					.iter()
+					.find(|window| window.hwnd == hwnd)
+					.map(|window| (process, window))
+			})
+			.map(|(process, window)| f(process, window))
+	}
+}
+
+impl WindowProvider for FakeProvider {
+	fn processes(&self, processes: &mut AHashMap<u32, Process>) {
+		processes.extend(
+			self.processes
+				.lock()
+				.iter()
+				.map(|process| (process.pid, process.clone())),
+		);
+	}
+
+	fn window_title(&self, hwnd: isize) -> String {
+		self.find_window(hwnd, |_, window| window.title.clone())
+			.unwrap_or_default()
+	}
+
+	fn window_pid(&self, hwnd: isize) -> Option<u32> {
+		self.find_window(hwnd, |process, _| process.pid)
+	}
+
+	fn is_window(&self, hwnd: isize) -> bool {
+		self.find_window(hwnd, |_, _| ()).is_some()
+	}
+
+	fn capture(&self, hwnd: isize, config: CaptureConfig) -> Option<Box<dyn Capture>> {
+		self.is_window(hwnd).then(|| {
+			Box::new(FakeCapture {
+				provider: self.clone(),
+				hwnd,
+				config,
+			}) as Box<dyn Capture>
+		})
+	}
+}
+
+/// A capture started by a [`FakeProvider`]. It stays active for as long as
+/// its window is in the provider's list, and is the size of the window.
+pub struct FakeCapture {
+	provider: FakeProvider,
+	hwnd: isize,
+	pub config: CaptureConfig,
+}
+
+impl Capture for FakeCapture {
+	fn active(&self) -> bool {
+		self.provider.is_window(self.hwnd)
+	}
+
+	fn render(&self) {}
+
+	fn width(&self) -> u32 {
+		self.provider
+			.find_window(self.hwnd, |_, window| window.width.max(0) as u32)
+			.unwrap_or_default()
+	}
+
+	fn height(&self) -> u32 {
+		self.provider
+			.find_window(self.hwnd, |_, window| window.height.max(0) as u32)
+			.unwrap_or_default()
+	}
+}
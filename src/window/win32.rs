@@ -0,0 +1,217 @@
+// SPDX-License-Identifier: MPL-2.0
+use super::{process_info, Process, Window, WindowProvider};
+use crate::{
+	capture::{Capture, CaptureConfig, WinrtCapture},
+	util::TrimInPlace,
+};
+use ahash::AHashMap;
+use windows::Win32::{
+	Foundation::{CloseHandle, BOOL, HWND, LPARAM, RECT},
+	Graphics::{
+		Dwm::{DwmGetWindowAttribute, DWMWA_CLOAKED},
+		Gdi::{EnumDisplayMonitors, MonitorFromWindow, HDC, HMONITOR, MONITOR_DEFAULTTONULL},
+	},
+	System::Diagnostics::ToolHelp::{
+		CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+		TH32CS_SNAPPROCESS,
+	},
+	UI::WindowsAndMessaging::{
+		EnumWindows, GetClassNameW, GetForegroundWindow, GetWindow, GetWindowLongW, GetWindowRect,
+		GetWindowTextW, IsIconic, IsWindow, IsWindowVisible, IsZoomed, GWL_EXSTYLE, GW_OWNER,
+		WINDOW_EX_STYLE, WS_EX_TOOLWINDOW,
+	},
+};
+use wtf8::Wtf8Buf;
+
+/// Reads processes and windows from the running Windows session.
+pub struct Win32Provider;
+
+impl WindowProvider for Win32Provider {
+	fn processes(&self, processes: &mut AHashMap<u32, Process>) {
+		get_processes(processes);
+	}
+
+	fn window_title(&self, hwnd: isize) -> String {
+		get_window_title(HWND(hwnd))
+	}
+
+	fn window_pid(&self, hwnd: isize) -> Option<u32> {
+		crate::util::hwnd_to_pid(hwnd)
+	}
+
+	fn is_window(&self, hwnd: isize) -> bool {
+		is_window(HWND(hwnd))
+	}
+
+	fn capture(&self, hwnd: isize, config: CaptureConfig) -> Option<Box<dyn Capture>> {
+		let capture = WinrtCapture::new(config.cursor, hwnd, config.client_area, config.force_sdr)?;
+		Some(Box::new(capture))
+	}
+}
+
+/// State shared by the calls to `enum_window` during one enumeration.
+struct Enumeration<'a> {
+	processes: &'a mut AHashMap<u32, Process>,
+	monitors: Vec<HMONITOR>,
+	foreground: HWND,
+	/// The z-order of the next window enumerated.
+	z_order: u32,
+}
+
+pub fn get_processes(processes: &mut AHashMap<u32, Process>) {
+	let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }
+		.expect("failed to create toolhelp32 snapshot");
+
+	let mut process_entry = PROCESSENTRY32W {
+		dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+		..PROCESSENTRY32W::default()
+	};
+
+	if unsafe { Process32FirstW(snapshot, &mut process_entry) }.is_ok() {
+		loop {
+			let pid = process_entry.th32ProcessID;
+			let len = process_entry
+				.szExeFile
+				.iter()
+				.position(|&c| c == 0)
+				.unwrap_or(0);
+			let process_name = Wtf8Buf::from_ill_formed_utf16(&process_entry.szExeFile[..len])
+				.into_string_lossy()
+				.trim_in_place();
+			let info = process_info::query(pid);
+			processes.insert(pid, Process {
+				name: process_name,
+				pid,
+				exe_path: info.exe_path,
+				command_line: info.command_line,
+				parent_pid: Some(process_entry.th32ParentProcessID),
+				start_time: info.start_time,
+				session_id: info.session_id,
+				user: info.user,
+				..Process::default()
+			});
+
+			if unsafe { Process32NextW(snapshot, &mut process_entry) }.is_err() {
+				break;
+			}
+		}
+	}
+	let _ = unsafe { CloseHandle(snapshot) };
+	process_info::retain(|pid| processes.contains_key(&pid));
+
+	let mut monitors = Vec::<HMONITOR>::new();
+	let param = LPARAM(&mut monitors as *mut _ as isize);
+	unsafe { EnumDisplayMonitors(HDC::default(), None, Some(enum_monitor), param) };
+
+	let mut enumeration = Enumeration {
+		processes,
+		monitors,
+		foreground: unsafe { GetForegroundWindow() },
+		z_order: 0,
+	};
+	let param = LPARAM(&mut enumeration as *mut _ as isize);
+	unsafe { EnumWindows(Some(enum_window), param) }.expect("failed to enumerate windows");
+}
+
+pub fn get_window_title(hwnd: HWND) -> String {
+	let mut buffer = [0; 256];
+	let len = unsafe { GetWindowTextW(hwnd, &mut buffer) as usize };
+	if len == 0 {
+		return String::new();
+	}
+	Wtf8Buf::from_ill_formed_utf16(&buffer[..len])
+		.into_string_lossy()
+		.trim_in_place()
+}
+
+pub fn is_window(hwnd: HWND) -> bool {
+	unsafe { IsWindow(hwnd) }.as_bool()
+}
+
+pub fn is_window_visible(hwnd: HWND) -> bool {
+	unsafe { IsWindowVisible(hwnd) }.as_bool()
+}
+
+unsafe extern "system" fn enum_monitor(
+	monitor: HMONITOR,
+	_: HDC,
+	_: *mut RECT,
+	lparam: LPARAM,
+) -> BOOL {
+	let monitors = &mut *(lparam.0 as *mut Vec<HMONITOR>);
+	monitors.push(monitor);
+	BOOL(1) // Continue enumeration
+}
+
+unsafe extern "system" fn enum_window(hwnd: HWND, lparam: LPARAM) -> BOOL {
+	let enumeration = &mut *(lparam.0 as *mut Enumeration);
+	let z_order = enumeration.z_order;
+	enumeration.z_order += 1;
+	let process_id = match crate::util::hwnd_to_pid(hwnd.0) {
+		Some(pid) => pid,
+		None => return BOOL(1), // Continue enumeration
+	};
+	if let Some(process) = enumeration.processes.get_mut(&process_id) {
+		let title = get_window_title(hwnd);
+		if title.is_empty() {
+			return BOOL(1); // Continue enumeration
+		}
+		let mut rect = RECT::default();
+		if GetWindowRect(hwnd, &mut rect).is_err() {
+			rect = RECT {
+				left: i32::MIN,
+				top: i32::MIN,
+				right: i32::MIN,
+				bottom: i32::MIN,
+			};
+		}
+		let class_name = {
+			let mut buffer = [0; 256];
+			let len = GetClassNameW(hwnd, &mut buffer) as usize;
+			Wtf8Buf::from_ill_formed_utf16(&buffer[..len])
+				.into_string_lossy()
+				.trim_in_place()
+		};
+		let mut cloaked = 0u32;
+		let cloaked = DwmGetWindowAttribute(
+			hwnd,
+			DWMWA_CLOAKED,
+			&mut cloaked as *mut u32 as *mut _,
+			std::mem::size_of::<u32>() as u32,
+		)
+		.is_ok() && cloaked != 0;
+		let ex_style = WINDOW_EX_STYLE(GetWindowLongW(hwnd, GWL_EXSTYLE) as u32);
+		let owner = GetWindow(hwnd, GW_OWNER);
+		let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONULL);
+		let window = Window {
+			title,
+			class_name,
+			hwnd: hwnd.0,
+			visible: is_window_visible(hwnd),
+			x: rect.left,
+			y: rect.top,
+			width: rect.right - rect.left,
+			height: rect.bottom - rect.top,
+			minimized: IsIconic(hwnd).as_bool(),
+			maximized: IsZoomed(hwnd).as_bool(),
+			cloaked,
+			is_tool_window: ex_style.contains(WS_EX_TOOLWINDOW),
+			owner_hwnd: (owner.0 != 0).then_some(owner.0),
+			z_order,
+			is_foreground: hwnd == enumeration.foreground,
+			monitor: enumeration
+				.monitors
+				.iter()
+				.position(|&m| m == monitor)
+				.map(|index| index as u32),
+		};
+
+		if window.visible && process.main.is_none() {
+			process.main = Some(window.clone());
+		}
+
+		process.windows.push(window);
+	}
+
+	BOOL(1) // Continue enumeration
+}
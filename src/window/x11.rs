@@ -0,0 +1,284 @@
+// SPDX-License-Identifier: MPL-2.0
+use super::{procfs::ProcfsProvider, Process, Window, WindowProvider};
+use crate::capture::{Capture, CaptureConfig};
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use x11rb::{
+	connection::Connection,
+	protocol::{
+		randr::ConnectionExt as _,
+		xproto::{Atom, AtomEnum, ConnectionExt, MapState, Window as XWindow},
+	},
+	rust_connection::RustConnection,
+};
+
+x11rb::atom_manager! {
+	Atoms: AtomsCookie {
+		_NET_ACTIVE_WINDOW,
+		_NET_CLIENT_LIST,
+		_NET_CLIENT_LIST_STACKING,
+		_NET_WM_NAME,
+		_NET_WM_PID,
+		_NET_WM_STATE,
+		_NET_WM_STATE_HIDDEN,
+		_NET_WM_STATE_MAXIMIZED_HORZ,
+		_NET_WM_STATE_MAXIMIZED_VERT,
+		_NET_WM_WINDOW_TYPE,
+		_NET_WM_WINDOW_TYPE_TOOLBAR,
+		_NET_WM_WINDOW_TYPE_UTILITY,
+		UTF8_STRING,
+	}
+}
+
+/// The monitor rectangle and active window, read once per poll.
+struct Desktop {
+	active: Option<XWindow>,
+	/// Monitors as `(x, y, width, height)`, in RandR's order.
+	monitors: Vec<(i32, i32, i32, i32)>,
+}
+
+/// Reads top-level windows from an X server through the EWMH hints its
+/// window manager publishes, and attaches them to the processes in `/proc`
+/// by `_NET_WM_PID`. Window ids stand in for hwnds.
+pub struct X11Provider {
+	connection: RustConnection,
+	root: XWindow,
+	atoms: Atoms,
+	processes: ProcfsProvider,
+}
+
+impl X11Provider {
+	/// Connects to the X server at `display`, such as `":99"` for a local
+	/// Xvfb, or the one named by `$DISPLAY` if `None`.
+	pub fn connect(display: Option<&str>) -> Result<Self> {
+		let (connection, screen) =
+			x11rb::connect(display).context("failed to connect to the X server")?;
+		let root = connection.setup().roots[screen].root;
+		let atoms = Atoms::new(&connection)
+			.context("failed to intern atoms")?
+			.reply()
+			.context("failed to intern atoms")?;
+		Ok(Self {
+			connection,
+			root,
+			atoms,
+			processes: ProcfsProvider::new(),
+		})
+	}
+
+	/// Gets the managed top-level windows, topmost first like `EnumWindows`,
+	/// falling back to mapping order if the window manager doesn't publish
+	/// the stacking order.
+	fn client_list(&self) -> Vec<XWindow> {
+		if let Some(mut stacking) = self
+			.property32(
+				self.root,
+				self.atoms._NET_CLIENT_LIST_STACKING,
+				AtomEnum::WINDOW,
+			)
+			.filter(|stacking| !stacking.is_empty())
+		{
+			stacking.reverse();
+			return stacking;
+		}
+		self.property32(self.root, self.atoms._NET_CLIENT_LIST, AtomEnum::WINDOW)
+			.unwrap_or_default()
+	}
+
+	fn property32(&self, window: XWindow, property: Atom, typ: AtomEnum) -> Option<Vec<u32>> {
+		let reply = self
+			.connection
+			.get_property(false, window, property, typ, 0, u32::MAX / 4)
+			.ok()?
+			.reply()
+			.ok()?;
+		let values = reply.value32()?.collect();
+		Some(values)
+	}
+
+	fn property8(&self, window: XWindow, property: Atom, typ: Atom) -> Option<Vec<u8>> {
+		let reply = self
+			.connection
+			.get_property(false, window, property, typ, 0, u32::MAX / 4)
+			.ok()?
+			.reply()
+			.ok()?;
+		let values = reply.value8()?.collect();
+		Some(values)
+	}
+
+	/// Gets a window's title from `_NET_WM_NAME`, or the Latin-1 `WM_NAME`
+	/// if it has none.
+	fn title(&self, window: XWindow) -> String {
+		if let Some(name) = self
+			.property8(window, self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING)
+			.filter(|name| !name.is_empty())
+		{
+			return String::from_utf8_lossy(&name).trim().to_owned();
+		}
+		self.property8(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())
+			.map(|name| name.iter().map(|&c| c as char).collect::<String>())
+			.unwrap_or_default()
+			.trim()
+			.to_owned()
+	}
+
+	/// Gets the class half of a window's `WM_CLASS`, which holds the
+	/// NUL-terminated instance name followed by the class name.
+	fn class_name(&self, window: XWindow) -> String {
+		self.property8(window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())
+			.and_then(|class| {
+				let mut parts = class.split(|&c| c == 0);
+				let instance = parts.next()?;
+				let class = parts.next().filter(|class| !class.is_empty());
+				Some(String::from_utf8_lossy(class.unwrap_or(instance)).into_owned())
+			})
+			.unwrap_or_default()
+	}
+
+	fn desktop(&self) -> Desktop {
+		let active = self
+			.property32(self.root, self.atoms._NET_ACTIVE_WINDOW, AtomEnum::WINDOW)
+			.and_then(|active| active.first().copied())
+			.filter(|&active| active != 0);
+		// Servers without RandR 1.5 can't list monitors.
+		let monitors = self
+			.connection
+			.randr_get_monitors(self.root, true)
+			.ok()
+			.and_then(|cookie| cookie.reply().ok())
+			.map(|reply| {
+				reply
+					.monitors
+					.iter()
+					.map(|monitor| {
+						(
+							monitor.x as i32,
+							monitor.y as i32,
+							monitor.width as i32,
+							monitor.height as i32,
+						)
+					})
+					.collect()
+			})
+			.unwrap_or_default();
+		Desktop { active, monitors }
+	}
+
+	fn pid(&self, window: XWindow) -> Option<u32> {
+		self.property32(window, self.atoms._NET_WM_PID, AtomEnum::CARDINAL)?
+			.first()
+			.copied()
+	}
+
+	/// Reads a window the way `enum_window` does on Windows, skipping it if
+	/// it has no title or owning pid.
+	fn window(&self, window: XWindow, z_order: u32, desktop: &Desktop) -> Option<(u32, Window)> {
+		let pid = self.pid(window)?;
+		let title = self.title(window);
+		if title.is_empty() {
+			return None;
+		}
+		let attributes = self
+			.connection
+			.get_window_attributes(window)
+			.ok()?
+			.reply()
+			.ok()?;
+		let geometry = self.connection.get_geometry(window).ok()?.reply().ok()?;
+		// Geometry is relative to the parent, which is usually a window
+		// manager frame, so translate the origin to root coordinates.
+		let origin = self
+			.connection
+			.translate_coordinates(window, self.root, 0, 0)
+			.ok()?
+			.reply()
+			.ok()?;
+		let (x, y) = (origin.dst_x as i32, origin.dst_y as i32);
+		let (width, height) = (geometry.width as i32, geometry.height as i32);
+		let state = self
+			.property32(window, self.atoms._NET_WM_STATE, AtomEnum::ATOM)
+			.unwrap_or_default();
+		let types = self
+			.property32(window, self.atoms._NET_WM_WINDOW_TYPE, AtomEnum::ATOM)
+			.unwrap_or_default();
+		let owner = self
+			.property32(window, AtomEnum::WM_TRANSIENT_FOR.into(), AtomEnum::WINDOW)
+			.and_then(|owner| owner.first().copied())
+			.filter(|&owner| owner != 0);
+		// Like `MonitorFromWindow`, pick the monitor the window overlaps most.
+		let monitor = desktop
+			.monitors
+			.iter()
+			.enumerate()
+			.map(|(index, &(mx, my, mw, mh))| {
+				let overlap_x = (x + width).min(mx + mw) - x.max(mx);
+				let overlap_y = (y + height).min(my + mh) - y.max(my);
+				(index, overlap_x.max(0) as i64 * overlap_y.max(0) as i64)
+			})
+			.filter(|&(_, overlap)| overlap > 0)
+			.max_by_key(|&(_, overlap)| overlap)
+			.map(|(index, _)| index as u32);
+		Some((pid, Window {
+			title,
+			class_name: self.class_name(window),
+			hwnd: window as isize,
+			visible: attributes.map_state == MapState::VIEWABLE,
+			x,
+			y,
+			width,
+			height,
+			minimized: state.contains(&self.atoms._NET_WM_STATE_HIDDEN),
+			maximized: state.contains(&self.atoms._NET_WM_STATE_MAXIMIZED_HORZ)
+				&& state.contains(&self.atoms._NET_WM_STATE_MAXIMIZED_VERT),
+			// X has no equivalent of DWM cloaking.
+			cloaked: false,
+			is_tool_window: types.contains(&self.atoms._NET_WM_WINDOW_TYPE_UTILITY)
+				|| types.contains(&self.atoms._NET_WM_WINDOW_TYPE_TOOLBAR),
+			owner_hwnd: owner.map(|owner| owner as isize),
+			z_order,
+			is_foreground: desktop.active == Some(window),
+			monitor,
+		}))
+	}
+}
+
+impl WindowProvider for X11Provider {
+	fn processes(&self, processes: &mut AHashMap<u32, Process>) {
+		self.processes.processes(processes);
+		let desktop = self.desktop();
+		for (z_order, window) in self.client_list().into_iter().enumerate() {
+			let (pid, window) = match self.window(window, z_order as u32, &desktop) {
+				Some(window) => window,
+				None => continue,
+			};
+			if let Some(process) = processes.get_mut(&pid) {
+				if window.visible && process.main.is_none() {
+					process.main = Some(window.clone());
+				}
+				process.windows.push(window);
+			}
+		}
+	}
+
+	fn window_title(&self, hwnd: isize) -> String {
+		self.title(hwnd as XWindow)
+	}
+
+	fn window_pid(&self, hwnd: isize) -> Option<u32> {
+		self.pid(hwnd as XWindow)
+	}
+
+	fn is_window(&self, hwnd: isize) -> bool {
+		self.connection
+			.get_window_attributes(hwnd as XWindow)
+			.ok()
+			.and_then(|cookie| cookie.reply().ok())
+			.is_some()
+	}
+
+	/// Capture is only supported on Windows.
+	fn capture(&self, _hwnd: isize, _config: CaptureConfig) -> Option<Box<dyn Capture>> {
+		None
+	}
+}
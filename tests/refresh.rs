@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Exercises the global provider and process list, so it is kept in its own
+//! test binary, away from tests that could run alongside it.
+use ahash::AHashMap;
+use obs_wincat::{
+	module::window::{refresh_processes, set_provider, snapshot},
+	window::{FakeProvider, Process, Window},
+};
+
+fn process(pid: u32, name: &str, hwnds: &[isize]) -> Process {
+	let windows = hwnds
+		.iter()
+		.map(|&hwnd| Window {
+			title: format!("{name} {hwnd:#x}"),
+			hwnd,
+			visible: true,
+			..Window::default()
+		})
+		.collect::<Vec<_>>();
+	Process {
+		name: name.to_owned(),
+		pid,
+		main: windows.first().cloned(),
+		windows,
+		..Process::default()
+	}
+}
+
+fn pids(processes: &[Process]) -> Vec<u32> {
+	let mut pids = processes
+		.iter()
+		.map(|process| process.pid)
+		.collect::<Vec<_>>();
+	pids.sort_unstable();
+	pids
+}
+
+#[test]
+fn publishes_a_new_snapshot_from_the_provider() {
+	let provider = FakeProvider::new(vec![
+		process(100, "Code.exe", &[0x10]),
+		process(200, "firefox.exe", &[0x20, 0x21]),
+	]);
+	set_provider(provider.clone());
+	let mut scratch = AHashMap::new();
+
+	refresh_processes(&mut scratch);
+	let first = snapshot();
+	assert_eq!(pids(&first), [100, 200]);
+	let firefox = first.iter().find(|process| process.pid == 200).unwrap();
+	assert_eq!(firefox.windows.len(), 2);
+	assert!(scratch.is_empty());
+
+	provider.set_processes(vec![process(300, "notepad.exe", &[0x30])]);
+	refresh_processes(&mut scratch);
+	assert_eq!(pids(&snapshot()), [300]);
+	// Snapshots already handed out are never changed.
+	assert_eq!(pids(&first), [100, 200]);
+
+	assert!(provider.close_window(0x30));
+	refresh_processes(&mut scratch);
+	let notepad = &snapshot()[0];
+	assert!(notepad.windows.is_empty());
+	assert!(notepad.main.is_none());
+}
@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: MPL-2.0
+use obs_wincat::{
+	capture::CaptureConfig,
+	selection::{self, Candidate, CaptureOptions, WindowRef},
+	window::{FakeProvider, Process, Window, WindowProvider},
+};
+
+fn window(hwnd: isize, title: &str) -> Window {
+	Window {
+		title: title.to_owned(),
+		class_name: "Chrome_WidgetWin_1".to_owned(),
+		hwnd,
+		visible: true,
+		width: 1280,
+		height: 720,
+		..Window::default()
+	}
+}
+
+fn process(pid: u32, name: &str, windows: Vec<Window>) -> Process {
+	Process {
+		name: name.to_owned(),
+		pid,
+		main: windows.first().cloned(),
+		windows,
+		..Process::default()
+	}
+}
+
+fn processes() -> Vec<Process> {
+	vec![
+		process(100, "Code.exe", vec![window(
+			0x10,
+			"main.rs - Visual Studio Code",
+		)]),
+		process(200, "firefox.exe", vec![
+			window(0x20, "Mozilla Firefox"),
+			window(0x21, "Library"),
+		]),
+	]
+}
+
+#[test]
+fn resolves_a_window_by_hwnd() {
+	let processes = processes();
+	let provider = FakeProvider::new(processes.clone());
+	let selection = Candidate::Window(WindowRef::Hwnd(0x21))
+		.resolve(&processes, &provider)
+		.unwrap();
+	assert_eq!(selection.window.title, "Library");
+	assert_eq!(selection.process, "firefox.exe");
+	assert!(selection.next_check.is_none());
+}
+
+#[test]
+fn keeps_the_options_returned_with_a_window() {
+	let processes = processes();
+	let provider = FakeProvider::new(processes.clone());
+	let selection = Candidate::WithOptions {
+		window: WindowRef::Table { hwnd: 0x10 },
+		next_check: Some(2.5),
+		options: CaptureOptions {
+			cursor: Some(false),
+			..CaptureOptions::default()
+		},
+	}
+	.resolve(&processes, &provider)
+	.unwrap();
+	assert_eq!(selection.window.hwnd, 0x10);
+	assert_eq!(selection.options.cursor, Some(false));
+	assert_eq!(selection.options.client_area, None);
+	assert_eq!(selection.next_check, Some(2.5));
+}
+
+#[test]
+fn clamps_the_next_check_returned_with_a_window() {
+	let processes = processes();
+	let provider = FakeProvider::new(processes.clone());
+	let resolve = |next_check| {
+		Candidate::WithOptions {
+			window: WindowRef::Hwnd(0x10),
+			next_check: Some(next_check),
+			options: CaptureOptions::default(),
+		}
+		.resolve(&processes, &provider)
+	};
+	assert_eq!(resolve(0.0).unwrap().next_check, Some(0.1));
+	assert_eq!(resolve(-5.0).unwrap().next_check, Some(0.1));
+	assert_eq!(resolve(1e9).unwrap().next_check, Some(3600.0));
+	let err = resolve(f32::NAN).unwrap_err();
+	assert!(err.to_string().contains("finite"), "{err}");
+}
+
+#[test]
+fn rejects_a_window_missing_from_the_snapshot() {
+	let processes = processes();
+	let provider = FakeProvider::new(processes.clone());
+	let err = Candidate::Window(WindowRef::Hwnd(0x99))
+		.resolve(&processes, &provider)
+		.unwrap_err();
+	assert!(err.to_string().contains("not in the current process list"));
+}
+
+#[test]
+fn rejects_a_window_that_has_closed() {
+	let processes = processes();
+	let provider = FakeProvider::new(processes.clone());
+	assert!(provider.close_window(0x10));
+	let err = Candidate::Window(WindowRef::Hwnd(0x10))
+		.resolve(&processes, &provider)
+		.unwrap_err();
+	assert!(err.to_string().contains("no longer exists"));
+}
+
+#[test]
+fn rejects_a_window_now_owned_by_another_process() {
+	let processes = processes();
+	// The hwnd has been reused by a window of a process that started since
+	// the snapshot was taken.
+	let provider = FakeProvider::new(vec![process(300, "notepad.exe", vec![window(
+		0x10,
+		"Untitled - Notepad",
+	)])]);
+	let err = Candidate::Window(WindowRef::Hwnd(0x10))
+		.resolve(&processes, &provider)
+		.unwrap_err();
+	assert!(err.to_string().contains("now belongs to pid 300"));
+}
+
+#[test]
+fn detects_title_changes() {
+	let processes = processes();
+	let provider = FakeProvider::new(processes.clone());
+	let captured = &processes[0].windows[0];
+	assert_eq!(selection::title_change(captured, &provider), None);
+	assert!(provider.set_title(0x10, "lib.rs - Visual Studio Code"));
+	assert_eq!(
+		selection::title_change(captured, &provider).as_deref(),
+		Some("lib.rs - Visual Studio Code")
+	);
+}
+
+#[test]
+fn ignores_windows_that_lost_their_title() {
+	let processes = processes();
+	let provider = FakeProvider::new(processes.clone());
+	let captured = &processes[0].windows[0];
+	assert!(provider.set_title(0x10, ""));
+	assert_eq!(selection::title_change(captured, &provider), None);
+	assert!(provider.close_window(0x10));
+	assert_eq!(selection::title_change(captured, &provider), None);
+}
+
+#[test]
+fn fake_captures_follow_their_window() {
+	let provider = FakeProvider::new(processes());
+	let config = CaptureConfig {
+		cursor: true,
+		client_area: false,
+		force_sdr: false,
+	};
+	assert!(provider.capture(0x99, config).is_none());
+	let capture = provider.capture(0x20, config).unwrap();
+	assert!(capture.active());
+	assert_eq!((capture.width(), capture.height()), (1280, 720));
+	assert!(provider.close_window(0x20));
+	assert!(!capture.active());
+}
@@ -0,0 +1,94 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Checks that the process and window views passed to scripts still behave
+//! like the plain tables scripts used to get.
+use mlua::Function;
+use obs_wincat::{
+	lua::{process_list, setup_luau_context},
+	window::{Process, Window},
+};
+use std::sync::Arc;
+
+fn snapshot() -> Arc<Vec<Process>> {
+	let main = Window {
+		title: "main.rs - Visual Studio Code".to_owned(),
+		hwnd: 0x10,
+		visible: true,
+		..Window::default()
+	};
+	Arc::new(vec![Process {
+		name: "Code.exe".to_owned(),
+		pid: 100,
+		main: Some(main.clone()),
+		windows: vec![main],
+		..Process::default()
+	}])
+}
+
+/// Runs `script`, a function of the process list, and returns what it
+/// returned.
+fn run<R: for<'lua> mlua::FromLuaMulti<'lua>>(script: &str) -> R {
+	let lua = setup_luau_context();
+	let func = lua.load(script).eval::<Function>().unwrap();
+	let procs = process_list(&lua, &snapshot()).unwrap();
+	func.call(procs).unwrap()
+}
+
+#[test]
+fn views_are_tables_to_type() {
+	let types: (String, String, String, String) = run(r#"
+		return function(procs)
+			local proc = procs[1]
+			return type(proc), type(proc.main), typeof(proc.main), type(proc.main.title)
+		end
+	"#);
+	assert_eq!(
+		types,
+		(
+			"table".to_owned(),
+			"table".to_owned(),
+			"Window".to_owned(),
+			"string".to_owned()
+		)
+	);
+}
+
+#[test]
+fn selects_with_the_old_table_check() {
+	let hwnd: Option<i64> = run(r#"
+		return function(procs)
+			for _, proc in ipairs(procs) do
+				if proc.name == "Code.exe" and type(proc.main) == "table" then
+					return proc.main.hwnd
+				end
+			end
+			return nil
+		end
+	"#);
+	assert_eq!(hwnd, Some(0x10));
+}
+
+#[test]
+fn pairs_and_iteration_list_the_fields() {
+	let (from_pairs, from_iter, title): (String, String, String) = run(r#"
+		return function(procs)
+			local function keys(...)
+				local keys = {}
+				for key in ... do
+					table.insert(keys, key)
+				end
+				table.sort(keys)
+				return table.concat(keys, ",")
+			end
+			local title
+			for key, value in pairs(procs[1].main) do
+				if key == "title" then
+					title = value
+				end
+			end
+			return keys(pairs(procs[1])), keys(procs[1]), title
+		end
+	"#);
+	assert_eq!(from_pairs, "main,name,pid,windows");
+	assert_eq!(from_iter, from_pairs);
+	assert_eq!(title, "main.rs - Visual Studio Code");
+}
@@ -0,0 +1,239 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Checks `X11Provider` against a local X server with a few dummy clients.
+//! It needs a server that no window manager is running on, since the test
+//! publishes the EWMH client list itself, named by `WINCAT_TEST_DISPLAY`:
+//!
+//! ```sh
+//! Xvfb :99 -screen 0 1920x1080x24 &
+//! WINCAT_TEST_DISPLAY=:99 cargo test --test x11 -- --ignored
+//! ```
+#![cfg(target_os = "linux")]
+use anyhow::Result;
+use obs_wincat::window::{Process, Window, WindowProvider, X11Provider};
+use x11rb::{
+	connection::Connection,
+	protocol::xproto::{
+		AtomEnum, ConnectionExt, CreateWindowAux, PropMode, Window as XWindow, WindowClass,
+	},
+	rust_connection::RustConnection,
+	wrapper::ConnectionExt as _,
+	COPY_DEPTH_FROM_PARENT,
+};
+
+/// The environment variable naming the display to test against.
+const DISPLAY_VAR: &str = "WINCAT_TEST_DISPLAY";
+
+fn display() -> String {
+	std::env::var(DISPLAY_VAR)
+		.unwrap_or_else(|_| panic!("{DISPLAY_VAR} must name an X display without a window manager"))
+}
+
+x11rb::atom_manager! {
+	Atoms: AtomsCookie {
+		_NET_ACTIVE_WINDOW,
+		_NET_CLIENT_LIST,
+		_NET_CLIENT_LIST_STACKING,
+		_NET_WM_NAME,
+		_NET_WM_PID,
+		_NET_WM_STATE,
+		_NET_WM_STATE_HIDDEN,
+		_NET_WM_STATE_MAXIMIZED_HORZ,
+		_NET_WM_STATE_MAXIMIZED_VERT,
+		_NET_WM_WINDOW_TYPE,
+		_NET_WM_WINDOW_TYPE_UTILITY,
+		UTF8_STRING,
+	}
+}
+
+/// Plays the part of both an application and its window manager.
+struct Clients {
+	connection: RustConnection,
+	root: XWindow,
+	atoms: Atoms,
+}
+
+impl Clients {
+	fn connect(display: &str) -> Result<Self> {
+		let (connection, screen) = x11rb::connect(Some(display))?;
+		let root = connection.setup().roots[screen].root;
+		let atoms = Atoms::new(&connection)?.reply()?;
+		Ok(Self {
+			connection,
+			root,
+			atoms,
+		})
+	}
+
+	/// Creates and maps a top-level window owned by this process.
+	fn create(&self, title: &str, class: &str, rect: (i16, i16, u16, u16)) -> Result<XWindow> {
+		let window = self.connection.generate_id()?;
+		let (x, y, width, height) = rect;
+		self.connection.create_window(
+			COPY_DEPTH_FROM_PARENT,
+			window,
+			self.root,
+			x,
+			y,
+			width,
+			height,
+			0,
+			WindowClass::INPUT_OUTPUT,
+			0,
+			&CreateWindowAux::new(),
+		)?;
+		self.connection.change_property8(
+			PropMode::REPLACE,
+			window,
+			self.atoms._NET_WM_NAME,
+			self.atoms.UTF8_STRING,
+			title.as_bytes(),
+		)?;
+		self.connection.change_property8(
+			PropMode::REPLACE,
+			window,
+			AtomEnum::WM_CLASS,
+			AtomEnum::STRING,
+			format!("{}\0{class}\0", class.to_lowercase()).as_bytes(),
+		)?;
+		self.connection.change_property32(
+			PropMode::REPLACE,
+			window,
+			self.atoms._NET_WM_PID,
+			AtomEnum::CARDINAL,
+			&[std::process::id()],
+		)?;
+		self.connection.map_window(window)?;
+		Ok(window)
+	}
+
+	fn set_atoms(&self, window: XWindow, property: u32, atoms: &[u32]) -> Result<()> {
+		self.connection.change_property32(
+			PropMode::REPLACE,
+			window,
+			property,
+			AtomEnum::ATOM,
+			atoms,
+		)?;
+		Ok(())
+	}
+
+	/// Publishes the hints a window manager would, with `stacking` from
+	/// bottom to top.
+	fn manage(&self, stacking: &[XWindow], active: XWindow) -> Result<()> {
+		for property in [
+			self.atoms._NET_CLIENT_LIST,
+			self.atoms._NET_CLIENT_LIST_STACKING,
+		] {
+			self.connection.change_property32(
+				PropMode::REPLACE,
+				self.root,
+				property,
+				AtomEnum::WINDOW,
+				stacking,
+			)?;
+		}
+		self.connection.change_property32(
+			PropMode::REPLACE,
+			self.root,
+			self.atoms._NET_ACTIVE_WINDOW,
+			AtomEnum::WINDOW,
+			&[active],
+		)?;
+		// Wait for the server to handle everything, so that the provider's
+		// own connection sees it.
+		self.connection.sync()?;
+		Ok(())
+	}
+}
+
+fn find(process: &Process, hwnd: XWindow) -> &Window {
+	process
+		.windows
+		.iter()
+		.find(|window| window.hwnd == hwnd as isize)
+		.unwrap_or_else(|| panic!("window {hwnd:#x} wasn't listed"))
+}
+
+#[test]
+#[ignore = "needs an X server named by WINCAT_TEST_DISPLAY, such as Xvfb"]
+fn reads_windows_from_the_x_server() -> Result<()> {
+	let display = display();
+	let clients = Clients::connect(&display)?;
+	let editor = clients.create("main.rs - Editor", "Editor", (10, 20, 800, 600))?;
+	let palette = clients.create("Tools", "Editor", (40, 50, 200, 300))?;
+	let hidden = clients.create("Minimized", "Viewer", (0, 0, 640, 480))?;
+	let untitled = clients.create("", "Viewer", (0, 0, 100, 100))?;
+	let atoms = &clients.atoms;
+	clients.set_atoms(editor, atoms._NET_WM_STATE, &[
+		atoms._NET_WM_STATE_MAXIMIZED_HORZ,
+		atoms._NET_WM_STATE_MAXIMIZED_VERT,
+	])?;
+	clients.set_atoms(palette, atoms._NET_WM_WINDOW_TYPE, &[
+		atoms._NET_WM_WINDOW_TYPE_UTILITY
+	])?;
+	clients.connection.change_property32(
+		PropMode::REPLACE,
+		palette,
+		AtomEnum::WM_TRANSIENT_FOR,
+		AtomEnum::WINDOW,
+		&[editor],
+	)?;
+	clients.set_atoms(hidden, atoms._NET_WM_STATE, &[atoms._NET_WM_STATE_HIDDEN])?;
+	clients.manage(&[hidden, untitled, editor, palette], editor)?;
+
+	let provider = X11Provider::connect(Some(&display))?;
+	let mut processes = ahash::AHashMap::new();
+	provider.processes(&mut processes);
+	let process = &processes[&std::process::id()];
+
+	// Windows without a title are skipped, and the rest are listed topmost
+	// first, like EnumWindows.
+	let hwnds = process
+		.windows
+		.iter()
+		.map(|window| window.hwnd)
+		.collect::<Vec<_>>();
+	assert_eq!(hwnds, [palette as isize, editor as isize, hidden as isize]);
+	assert_eq!(
+		process.main.as_ref().map(|main| main.hwnd),
+		Some(palette as isize)
+	);
+
+	let window = find(process, editor);
+	assert_eq!(window.title, "main.rs - Editor");
+	assert_eq!(window.class_name, "Editor");
+	assert!(window.visible);
+	assert_eq!(
+		(window.x, window.y, window.width, window.height),
+		(10, 20, 800, 600)
+	);
+	assert!(window.maximized);
+	assert!(!window.minimized);
+	assert!(window.is_foreground);
+	assert!(!window.is_tool_window);
+	assert_eq!(window.owner_hwnd, None);
+	assert_eq!(window.z_order, 1);
+	assert_eq!(window.monitor, Some(0));
+
+	let window = find(process, palette);
+	assert!(window.is_tool_window);
+	assert_eq!(window.owner_hwnd, Some(editor as isize));
+	assert!(!window.is_foreground);
+	assert_eq!(window.z_order, 0);
+
+	let window = find(process, hidden);
+	assert!(window.minimized);
+	assert!(!window.maximized);
+	assert_eq!(window.z_order, 3);
+
+	assert_eq!(provider.window_title(editor as isize), "main.rs - Editor");
+	assert_eq!(
+		provider.window_pid(editor as isize),
+		Some(std::process::id())
+	);
+	assert!(provider.is_window(editor as isize));
+	clients.connection.destroy_window(editor)?;
+	clients.connection.sync()?;
+	assert!(!provider.is_window(editor as isize));
+	Ok(())
+}